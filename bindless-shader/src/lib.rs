@@ -0,0 +1,62 @@
+//! The fragment shaders of `UiPassDescriptor::bindless_textures`.
+//!
+//! Their texture arrays need the `RuntimeDescriptorArray` capability, which devices without
+//! descriptor indexing reject, so they are built into a module of their own that is only loaded
+//! by passes using bindless textures.
+
+#![cfg_attr(
+    target_arch = "spirv",
+    no_std,
+    feature(register_attr),
+    register_attr(spirv)
+)]
+
+#[cfg(not(target_arch = "spirv"))]
+use spirv_std::macros::spirv;
+
+use spirv_std::{Image, RuntimeArray, Sampler};
+
+use shader::glam::{Vec2, Vec4};
+use shader::{srgb_from_linear_premultiplied, PushConstants};
+
+/// `main_fs` sampling the texture selected by `PushConstants::texture_index`.
+#[spirv(fragment)]
+pub fn main_fs_bindless(
+    v_tex_coord: Vec2,
+    v_color: Vec4,
+    #[spirv(descriptor_set = 1, binding = 0)] samplers: &RuntimeArray<Sampler>,
+    #[spirv(descriptor_set = 1, binding = 1)] textures: &RuntimeArray<
+        Image!(2D, type=f32, sampled),
+    >,
+    #[spirv(push_constant)] push_constants: &PushConstants,
+    output: &mut Vec4,
+) {
+    let index = push_constants.texture_index as usize;
+    // Safety: UiPass only pushes indices of written entries.
+    let color: Vec4 = unsafe {
+        let sampler = *samplers.index(index);
+        textures.index(index).sample(sampler, v_tex_coord)
+    };
+    *output = v_color * color;
+}
+
+/// `main_fs_unorm` sampling the texture selected by `PushConstants::texture_index`.
+#[spirv(fragment)]
+pub fn main_fs_unorm_bindless(
+    v_tex_coord: Vec2,
+    v_color: Vec4,
+    #[spirv(descriptor_set = 1, binding = 0)] samplers: &RuntimeArray<Sampler>,
+    #[spirv(descriptor_set = 1, binding = 1)] textures: &RuntimeArray<
+        Image!(2D, type=f32, sampled),
+    >,
+    #[spirv(push_constant)] push_constants: &PushConstants,
+    output: &mut Vec4,
+) {
+    let index = push_constants.texture_index as usize;
+    // Safety: UiPass only pushes indices of written entries.
+    let color: Vec4 = unsafe {
+        let sampler = *samplers.index(index);
+        textures.index(index).sample(sampler, v_tex_coord)
+    };
+    *output = srgb_from_linear_premultiplied(v_color * color);
+}
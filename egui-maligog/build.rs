@@ -3,4 +3,12 @@ fn main() {
         .capability(spirv_builder::Capability::Int8)
         .build()
         .unwrap();
+    // Kept apart so that passes without bindless textures load no module needing descriptor
+    // indexing.
+    spirv_builder::SpirvBuilder::new("../bindless-shader", "spirv-unknown-vulkan1.2")
+        .capability(spirv_builder::Capability::Int8)
+        .capability(spirv_builder::Capability::RuntimeDescriptorArray)
+        .extension("SPV_EXT_descriptor_indexing")
+        .build()
+        .unwrap();
 }
@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use maligog::{vk, Device};
+
+use crate::upload::PendingUpload;
+
+/// The egui font texture on the GPU, together with the copy of its pixels it was last updated
+/// from.
+///
+/// The image has a single `R8_UNORM` channel. It is sampled through a view that swizzles the
+/// coverage into every component, so no RGBA copy has to be made on the CPU. Pixels are copied
+/// by the [`PendingUpload`]s the atlas returns.
+pub(crate) struct FontAtlas {
+    image: maligog::Image,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl FontAtlas {
+    pub fn new(device: &Device, egui_texture: &egui::Texture) -> (Self, PendingUpload) {
+        let image = device.create_image(
+            Some("egui texture"),
+            vk::Format::R8_UNORM,
+            egui_texture.width as u32,
+            egui_texture.height as u32,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            maligog::MemoryLocation::GpuOnly,
+        );
+        let upload = PendingUpload::new(
+            device,
+            &image,
+            vk::ImageLayout::UNDEFINED,
+            &egui_texture.pixels,
+            0,
+            egui_texture.width as u32,
+            egui_texture.height as u32,
+        );
+        let atlas = Self {
+            image,
+            width: egui_texture.width,
+            height: egui_texture.height,
+            pixels: egui_texture.pixels.clone(),
+        };
+        (atlas, upload)
+    }
+
+    /// Whether `egui_texture` can be uploaded into this atlas without creating a new image.
+    pub fn fits(&self, egui_texture: &egui::Texture) -> bool {
+        self.width == egui_texture.width && self.height == egui_texture.height
+    }
+
+    /// Returns the upload of the rows that differ from the last update, if any.
+    ///
+    /// egui only adds glyphs to rows no earlier frame has drawn from, so frames still in flight
+    /// are not affected by the copy.
+    pub fn update(
+        &mut self,
+        device: &Device,
+        egui_texture: &egui::Texture,
+    ) -> Option<PendingUpload> {
+        debug_assert!(self.fits(egui_texture));
+        let rows = changed_rows(&self.pixels, &egui_texture.pixels, self.width)?;
+        let bytes = &egui_texture.pixels[rows.start * self.width..rows.end * self.width];
+        let upload = PendingUpload::new(
+            device,
+            &self.image,
+            vk::ImageLayout::READ_ONLY_OPTIMAL_KHR,
+            bytes,
+            rows.start as u32,
+            self.width as u32,
+            rows.len() as u32,
+        );
+        self.pixels[rows.start * self.width..rows.end * self.width].copy_from_slice(bytes);
+        Some(upload)
+    }
+
+    /// A view that reads the coverage in all four channels, like the RGBA texture egui expects.
+    pub fn create_view(&self) -> maligog::ImageView {
+        self.image
+            .create_view_with_components(vk::ComponentMapping {
+                r: vk::ComponentSwizzle::R,
+                g: vk::ComponentSwizzle::R,
+                b: vk::ComponentSwizzle::R,
+                a: vk::ComponentSwizzle::R,
+            })
+    }
+}
+
+/// The range of rows that differ between two images of the same size.
+fn changed_rows(old: &[u8], new: &[u8], width: usize) -> Option<Range<usize>> {
+    let mut rows = old
+        .chunks_exact(width)
+        .zip(new.chunks_exact(width))
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(row, _)| row);
+    let first = rows.next()?;
+    let last = rows.last().unwrap_or(first);
+    Some(first..last + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changed_rows;
+
+    #[test]
+    fn unchanged_image_has_no_rows() {
+        let pixels = vec![7; 12];
+        assert_eq!(changed_rows(&pixels, &pixels, 4), None);
+    }
+
+    #[test]
+    fn range_spans_first_to_last_changed_row() {
+        let old = vec![0; 16];
+        let mut new = old.clone();
+        new[5] = 1;
+        new[10] = 1;
+        assert_eq!(changed_rows(&old, &new, 4), Some(1..3));
+    }
+}
@@ -0,0 +1,127 @@
+use maligog::vk;
+
+/// Pipeline stages and the memory accesses they make, on one side of a barrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessScope {
+    pub stage_mask: vk::PipelineStageFlags,
+    pub access_mask: vk::AccessFlags,
+}
+
+impl AccessScope {
+    pub const COLOR_ATTACHMENT_WRITE: Self = Self {
+        stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    };
+    /// No work to order with, e.g. for a new image, or before presenting, which is ordered by a
+    /// semaphore.
+    pub const NONE: Self = Self {
+        stage_mask: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        access_mask: vk::AccessFlags::empty(),
+    };
+    pub const TRANSFER_READ: Self = Self {
+        stage_mask: vk::PipelineStageFlags::TRANSFER,
+        access_mask: vk::AccessFlags::TRANSFER_READ,
+    };
+}
+
+/// How the work around a [`UiPass`](crate::UiPass) uses its color and depth attachments, which
+/// the pass synchronizes with.
+///
+/// For a pass with a [`DepthDescriptor`](crate::DepthDescriptor), the depth tests and writes of
+/// that work are added to both sides, so the scopes only need to describe the color attachment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TargetBarriers {
+    /// Commands before the pass that access the attachments, which the UI waits for.
+    pub src: AccessScope,
+    /// Commands after the pass that access the attachments, which wait for the UI.
+    pub dst: AccessScope,
+}
+
+impl Default for TargetBarriers {
+    /// Drawn over a scene rendered just before, or into a swapchain image acquired with a
+    /// semaphore waited on at `COLOR_ATTACHMENT_OUTPUT`, and presented afterwards.
+    fn default() -> Self {
+        Self {
+            src: AccessScope::COLOR_ATTACHMENT_WRITE,
+            dst: AccessScope::NONE,
+        }
+    }
+}
+
+impl AccessScope {
+    fn union(self, other: Self) -> Self {
+        Self {
+            stage_mask: self.stage_mask | other.stage_mask,
+            access_mask: self.access_mask | other.access_mask,
+        }
+    }
+}
+
+impl TargetBarriers {
+    /// The barriers of a pass, including the depth attachment accesses of the work around it if
+    /// the pass has one.
+    pub(crate) fn for_attachments(self, depth: bool) -> Self {
+        if !depth {
+            return self;
+        }
+        let depth_attachment = AccessScope {
+            stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        };
+        Self {
+            src: self.src.union(depth_attachment),
+            dst: self.dst.union(depth_attachment),
+        }
+    }
+}
+
+/// The stages and accesses of the pass itself on its attachments.
+pub(crate) fn attachment_scope(depth: bool) -> AccessScope {
+    let mut scope = AccessScope {
+        stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    };
+    if depth {
+        scope.stage_mask |= vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        scope.access_mask |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    }
+    scope
+}
+
+/// Records a barrier between `src` and `dst` that also moves the whole of `image` from
+/// `old_layout` to `new_layout`.
+pub(crate) fn image_barrier(
+    recorder: &mut maligog::CommandRecorder,
+    image: &maligog::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src: AccessScope,
+    dst: AccessScope,
+) {
+    recorder.pipeline_barrier(
+        src.stage_mask,
+        dst.stage_mask,
+        &[vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src.access_mask)
+            .dst_access_mask(dst.access_mask)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image.handle())
+            .subresource_range(
+                vk::ImageSubresourceRange::builder()
+                    .aspect_mask(aspect_mask)
+                    .level_count(1)
+                    .layer_count(1)
+                    .build(),
+            )
+            .build()],
+    );
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use maligog::{vk, Device};
+use maplit::btreemap;
+
+use crate::descriptor::DescriptorAllocator;
+use crate::{Error, Result};
+
+/// Slot of the egui font texture.
+pub(crate) const FONT_SLOT: u32 = 0;
+/// Slot of the checkerboard drawn in place of missing textures.
+pub(crate) const PLACEHOLDER_SLOT: u32 = 1;
+
+/// Slots taken by the font and placeholder, user textures start after them.
+const RESERVED_SLOTS: u32 = 2;
+
+/// Every texture of a [`UiPass`](crate::UiPass) in one descriptor array, indexed by slot.
+///
+/// Each frame in flight has its own set, which is rewritten the next time the frame is prepared
+/// after any slot changed, so a set is never written while the GPU may read it. Every entry of
+/// the arrays is written, empty slots repeat another texture, so the sets are complete without
+/// `PARTIALLY_BOUND`.
+///
+/// User textures take the lowest free slot when they are registered. The slot of a freed texture
+/// is taken again once the frames in flight that may have sampled it are done.
+pub(crate) struct BindlessTextures {
+    slots: Vec<Option<(maligog::ImageView, maligog::Sampler)>>,
+    /// Slot of every registered user texture id.
+    user_slots: HashMap<u64, u32>,
+    /// User slots that are not taken, the lowest last.
+    free_slots: Vec<u32>,
+    /// Slots of freed user textures and the frame number they were freed in.
+    retired_slots: Vec<(u64, u32)>,
+    /// Incremented whenever a slot changes.
+    generation: u64,
+    descriptors: DescriptorAllocator,
+    /// The set of each frame in flight and the generation last written to it.
+    frame_sets: Vec<(maligog::DescriptorSet, u64)>,
+}
+
+impl BindlessTextures {
+    pub fn new(device: &Device, capacity: u32, frames_in_flight: usize) -> Self {
+        let layout = device.create_descriptor_set_layout(
+            Some("bindless textures"),
+            &[
+                maligog::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: maligog::DescriptorType::Sampler(None),
+                    stage_flags: maligog::ShaderStageFlags::FRAGMENT,
+                    descriptor_count: capacity,
+                    variable_count: false,
+                },
+                maligog::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: maligog::DescriptorType::SampledImage,
+                    stage_flags: maligog::ShaderStageFlags::FRAGMENT,
+                    descriptor_count: capacity,
+                    variable_count: false,
+                },
+            ],
+        );
+        let descriptors = DescriptorAllocator::new(
+            device,
+            "bindless texture descriptor set",
+            &layout,
+            &[
+                (vk::DescriptorType::SAMPLER, capacity),
+                (vk::DescriptorType::SAMPLED_IMAGE, capacity),
+            ],
+        );
+        let mut bindless = Self {
+            slots: vec![None; capacity as usize],
+            user_slots: HashMap::new(),
+            free_slots: (RESERVED_SLOTS..capacity).rev().collect(),
+            retired_slots: Vec::new(),
+            generation: 0,
+            descriptors,
+            frame_sets: Vec::new(),
+        };
+        bindless.set_frames_in_flight(frames_in_flight);
+        bindless
+    }
+
+    pub fn layout(&self) -> &maligog::DescriptorSetLayout {
+        self.descriptors.layout()
+    }
+
+    /// Takes a free slot for a newly registered user texture id.
+    pub fn allocate_user_slot(&mut self, id: u64) -> Result<u32> {
+        let slot = self.free_slots.pop().ok_or(Error::TooManyTextures {
+            capacity: self.slots.len() as u32,
+        })?;
+        self.user_slots.insert(id, slot);
+        Ok(slot)
+    }
+
+    /// Slot of a registered user texture id that has not been freed.
+    pub fn user_slot(&self, id: u64) -> Option<u32> {
+        self.user_slots.get(&id).copied()
+    }
+
+    /// Empties the slot of a user texture freed during `frame_number`.
+    pub fn free_user_slot(&mut self, id: u64, frame_number: u64) {
+        if let Some(slot) = self.user_slots.remove(&id) {
+            self.clear(slot);
+            self.retired_slots.push((frame_number, slot));
+        }
+    }
+
+    /// Makes the slots of freed textures available again once no frame in flight can sample
+    /// them.
+    pub fn recycle_slots(&mut self, frame_number: u64, frames_in_flight: u64) {
+        let (done, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.retired_slots)
+            .into_iter()
+            .partition(|(retired, _)| frame_number >= retired + frames_in_flight);
+        self.retired_slots = pending;
+        self.free_slots
+            .extend(done.into_iter().map(|(_, slot)| slot));
+        // Lowest last, so low slots are taken first.
+        self.free_slots.sort_unstable_by(|a, b| b.cmp(a));
+    }
+
+    pub fn set(&mut self, slot: u32, image_view: maligog::ImageView, sampler: maligog::Sampler) {
+        self.slots[slot as usize] = Some((image_view, sampler));
+        self.generation += 1;
+    }
+
+    fn clear(&mut self, slot: u32) {
+        if let Some(entry) = self.slots.get_mut(slot as usize) {
+            if entry.take().is_some() {
+                self.generation += 1;
+            }
+        }
+    }
+
+    /// The GPU must be idle when the number of frames changes.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        for (descriptor_set, _) in self.frame_sets.drain(..) {
+            self.descriptors.free(descriptor_set);
+        }
+        for _ in 0..frames_in_flight {
+            let descriptor_set = self.descriptors.allocate(btreemap! {});
+            // Written by the first `prepare`.
+            self.frame_sets.push((descriptor_set, u64::MAX));
+        }
+    }
+
+    /// Writes the current slots to the set of `frame`, which the GPU must be done with.
+    pub fn prepare(&mut self, frame: usize) {
+        let (descriptor_set, written) = &mut self.frame_sets[frame];
+        if *written == self.generation {
+            return;
+        }
+        // Empty slots repeat the first texture, they are never sampled. Nothing can be drawn
+        // before the font texture is set.
+        let filler = match self.slots.iter().flatten().next() {
+            Some(filler) => filler,
+            None => return,
+        };
+        let (image_views, samplers): (Vec<_>, Vec<_>) = self
+            .slots
+            .iter()
+            .map(|slot| slot.as_ref().unwrap_or(filler).clone())
+            .unzip();
+        descriptor_set.update(btreemap! {
+            0 => maligog::DescriptorUpdate::Sampler(samplers),
+            1 => maligog::DescriptorUpdate::Image(image_views)
+        });
+        *written = self.generation;
+    }
+
+    pub fn descriptor_set(&self, frame: usize) -> &maligog::DescriptorSet {
+        &self.frame_sets[frame].0
+    }
+}
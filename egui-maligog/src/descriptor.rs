@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use maligog::{vk, Device};
+
+/// Number of sets the first pool is created with. Every following pool doubles in size.
+const INITIAL_SETS_PER_POOL: u32 = 16;
+
+/// Allocates descriptor sets of a single layout, creating new pools on demand.
+///
+/// Freed sets are kept in a free list and handed out again, with their descriptors rewritten,
+/// before any new set is allocated.
+pub(crate) struct DescriptorAllocator {
+    device: Device,
+    name: &'static str,
+    layout: maligog::DescriptorSetLayout,
+    /// Descriptor counts needed by one set of `layout`.
+    descriptor_counts: Vec<(vk::DescriptorType, u32)>,
+    pools: Vec<maligog::DescriptorPool>,
+    sets_per_pool: u32,
+    /// Sets still available in the last pool of `pools`.
+    remaining_sets: u32,
+    free_sets: Vec<maligog::DescriptorSet>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(
+        device: &Device,
+        name: &'static str,
+        layout: &maligog::DescriptorSetLayout,
+        descriptor_counts: &[(vk::DescriptorType, u32)],
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            name,
+            layout: layout.clone(),
+            descriptor_counts: descriptor_counts.to_vec(),
+            pools: Vec::new(),
+            sets_per_pool: INITIAL_SETS_PER_POOL / 2,
+            remaining_sets: 0,
+            free_sets: Vec::new(),
+        }
+    }
+
+    pub fn layout(&self) -> &maligog::DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Returns a set with `updates` written to it, recycling a freed set if there is one.
+    pub fn allocate(
+        &mut self,
+        updates: BTreeMap<u32, maligog::DescriptorUpdate>,
+    ) -> maligog::DescriptorSet {
+        if let Some(descriptor_set) = self.free_sets.pop() {
+            descriptor_set.update(updates);
+            return descriptor_set;
+        }
+
+        if self.remaining_sets == 0 {
+            self.grow();
+        }
+        self.remaining_sets -= 1;
+
+        self.device.create_descriptor_set(
+            Some(self.name),
+            self.pools.last().unwrap(),
+            &self.layout,
+            updates,
+        )
+    }
+
+    /// Returns `descriptor_set` to the free list. The caller must make sure the GPU is done with it.
+    pub fn free(&mut self, descriptor_set: maligog::DescriptorSet) {
+        self.free_sets.push(descriptor_set);
+    }
+
+    fn grow(&mut self) {
+        self.sets_per_pool *= 2;
+        let pool_sizes = self
+            .descriptor_counts
+            .iter()
+            .map(|(ty, count)| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(*ty)
+                    .descriptor_count(count * self.sets_per_pool)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        log::debug!(
+            "allocating {} descriptor pool with {} sets",
+            self.name,
+            self.sets_per_pool
+        );
+        self.pools.push(
+            self.device
+                .create_descriptor_pool(&pool_sizes, self.sets_per_pool),
+        );
+        self.remaining_sets = self.sets_per_pool;
+    }
+}
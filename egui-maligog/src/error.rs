@@ -0,0 +1,76 @@
+use std::fmt;
+
+use maligog::vk;
+
+/// Errors reported by [`UiPass`](crate::UiPass).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A paint job uses the egui font texture before [`UiPass::update_texture`] was called.
+    ///
+    /// [`UiPass::update_texture`]: crate::UiPass::update_texture
+    MissingFontTexture,
+    /// A paint job or call references a user texture id that was never registered.
+    UnknownUserTexture(u64),
+    /// A paint job references a user texture that has been freed.
+    FreedUserTexture(u64),
+    /// The font texture id was passed where a user texture is expected.
+    NotAUserTexture,
+    /// The pixel data of a texture does not match its dimensions.
+    InvalidTextureSize { width: u32, height: u32, len: usize },
+    /// The [`UiPassDescriptor`](crate::UiPassDescriptor) cannot be used for this operation.
+    InvalidDescriptor(&'static str),
+    /// The [`ScreenDescriptor`](crate::ScreenDescriptor) has a zero size or scale factor.
+    InvalidScreenDescriptor,
+    /// [`UiPass::execute_with_depth`](crate::UiPass::execute_with_depth) was not given one depth
+    /// per paint job.
+    DepthCountMismatch { paint_jobs: usize, depths: usize },
+    /// A frame is recorded with other paint jobs than [`UiPass::update_buffers`] uploaded for
+    /// it.
+    ///
+    /// [`UiPass::update_buffers`]: crate::UiPass::update_buffers
+    PaintJobCountMismatch { uploaded: usize, recorded: usize },
+    /// A user texture does not fit into the array of
+    /// [`UiPassDescriptor::bindless_textures`](crate::UiPassDescriptor::bindless_textures).
+    TooManyTextures { capacity: u32 },
+    /// Acquiring or presenting a swapchain image failed, or the swapchain stayed out of date
+    /// after being recreated.
+    Swapchain(vk::Result),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingFontTexture => {
+                write!(f, "egui texture was not set before the first draw")
+            }
+            Error::UnknownUserTexture(id) => write!(f, "user texture {} not found", id),
+            Error::FreedUserTexture(id) => write!(f, "user texture {} freed", id),
+            Error::NotAUserTexture => write!(f, "the egui font texture is not a user texture"),
+            Error::InvalidTextureSize { width, height, len } => {
+                write!(
+                    f,
+                    "{} bytes of pixel data for a {}x{} texture",
+                    len, width, height
+                )
+            }
+            Error::InvalidDescriptor(reason) => write!(f, "invalid UiPassDescriptor: {}", reason),
+            Error::InvalidScreenDescriptor => {
+                write!(f, "screen descriptor has a zero size or scale factor")
+            }
+            Error::DepthCountMismatch { paint_jobs, depths } => {
+                write!(f, "{} depths for {} paint jobs", depths, paint_jobs)
+            }
+            Error::PaintJobCountMismatch { uploaded, recorded } => {
+                write!(f, "{} paint jobs recorded, {} uploaded", recorded, uploaded)
+            }
+            Error::TooManyTextures { capacity } => {
+                write!(f, "more than {} bindless textures", capacity)
+            }
+            Error::Swapchain(err) => write!(f, "swapchain error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,156 @@
+use maligog::vk;
+
+use crate::{
+    AccessScope, Error, OutputColorSpace, Result, ScreenDescriptor, TargetBarriers, UiPass,
+    UiPassDescriptor, UiPassStats,
+};
+
+impl UiPassDescriptor {
+    /// Describes an offscreen RGBA8 target that is read back by [`UiPass::render_to_image`].
+    ///
+    /// Returns [`Error::InvalidDescriptor`] for formats other than `R8G8B8A8_UNORM` and
+    /// `R8G8B8A8_SRGB`.
+    pub fn headless(format: vk::Format) -> Result<Self> {
+        if format != vk::Format::R8G8B8A8_UNORM && format != vk::Format::R8G8B8A8_SRGB {
+            return Err(Error::InvalidDescriptor(
+                "headless rendering reads back RGBA8 pixels",
+            ));
+        }
+        Ok(Self {
+            format,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            // Earlier frames were waited on and the old contents are discarded. The image is read
+            // back by a copy afterwards.
+            target_barriers: TargetBarriers {
+                src: AccessScope::NONE,
+                dst: AccessScope::TRANSFER_READ,
+            },
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            frames_in_flight: 1,
+            output_color_space: OutputColorSpace::for_format(format),
+            ..Self::default()
+        })
+    }
+}
+
+/// The offscreen image and readback buffer of [`UiPass::render_to_image`], reused while the size
+/// and format stay the same.
+pub(crate) struct HeadlessTarget {
+    image: maligog::Image,
+    readback_buffer: maligog::Buffer,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+}
+
+impl HeadlessTarget {
+    fn new(device: &maligog::Device, format: vk::Format, width: u32, height: u32) -> Self {
+        Self {
+            image: device.create_image(
+                Some("egui offscreen target"),
+                format,
+                width,
+                height,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                maligog::MemoryLocation::GpuOnly,
+            ),
+            readback_buffer: device.create_buffer(
+                Some("egui readback buffer"),
+                (width * height * 4) as usize,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                maligog::MemoryLocation::GpuToCpu,
+            ),
+            width,
+            height,
+            format,
+        }
+    }
+
+    fn fits(&self, format: vk::Format, width: u32, height: u32) -> bool {
+        self.format == format && self.width == width && self.height == height
+    }
+}
+
+impl UiPass {
+    /// Renders `paint_jobs` into an offscreen image and returns its pixels as tightly packed RGBA8
+    /// rows.
+    ///
+    /// The image and the buffer it is read back through are kept for the next call, and only
+    /// created again when the size or format changes.
+    ///
+    /// The pass must have been created with [`UiPassDescriptor::headless`] and the font texture
+    /// set with [`UiPass::update_texture`]. The frame is submitted and waited on before returning,
+    /// and only core Vulkan 1.0 features are used so it runs on software implementations such
+    /// as lavapipe.
+    pub fn render_to_image(
+        &mut self,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: [f32; 4],
+    ) -> Result<Vec<u8>> {
+        if self.descriptor.final_layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+            return Err(Error::InvalidDescriptor(
+                "render_to_image needs a pass created with UiPassDescriptor::headless",
+            ));
+        }
+
+        let width = screen_descriptor.physical_width;
+        let height = screen_descriptor.physical_height;
+        let format = self.descriptor.format;
+        let target = match self.headless_target.take() {
+            Some(target) if target.fits(format, width, height) => target,
+            _ => HeadlessTarget::new(&self.device, format, width, height),
+        };
+        let image = target.image.clone();
+        let readback_buffer = target.readback_buffer.clone();
+        self.headless_target = Some(target);
+
+        self.update_buffers(0, paint_jobs, screen_descriptor)?;
+
+        let mut cmd_buf = self.device.create_command_buffer(
+            Some("egui offscreen command buffer"),
+            self.device.graphics_queue_family_index(),
+        );
+        let mut result = Ok(UiPassStats::default());
+        cmd_buf.encode(|recorder| {
+            result = self.execute(
+                0,
+                recorder,
+                &image,
+                paint_jobs,
+                screen_descriptor,
+                Some(vk::ClearColorValue {
+                    float32: clear_color,
+                }),
+            );
+            if result.is_err() {
+                return;
+            }
+            recorder.copy_image_to_buffer(
+                &image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                &readback_buffer,
+                &[vk::BufferImageCopy::builder()
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    })
+                    .build()],
+            );
+        });
+        result?;
+        self.device.graphics_queue().submit_blocking(&[cmd_buf]);
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        readback_buffer.copy_to(&mut pixels);
+        Ok(pixels)
+    }
+}
@@ -1,13 +1,48 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused))]
 
+use std::collections::HashSet;
+
 use bytemuck::{Pod, Zeroable};
 
-use maligog::{vk, BufferView, DescriptorSet, Device};
+use maligog::{vk, BufferView, Device};
 use maplit::btreemap;
 
 pub use egui;
 
+mod atlas;
+mod barrier;
+mod bindless;
+mod descriptor;
+mod error;
+mod headless;
+mod present;
+mod reference;
+mod sampler;
+mod stats;
+mod target;
+mod upload;
+#[cfg(feature = "winit")]
+mod winit;
+
+use atlas::FontAtlas;
+pub use barrier::{AccessScope, TargetBarriers};
+use bindless::BindlessTextures;
+use descriptor::DescriptorAllocator;
+pub use error::{Error, Result};
+use headless::HeadlessTarget;
+pub use present::Presenter;
+pub use reference::ReferenceRenderer;
+use sampler::SamplerCache;
+pub use sampler::SamplerDescriptor;
+use stats::GpuTimer;
+pub use stats::UiPassStats;
+use target::TargetCache;
+use upload::PendingUpload;
+#[cfg(feature = "winit")]
+pub use winit::EguiWinitRenderer;
+
 const SHADER: &[u8] = include_bytes!(env!("shader.spv"));
+const BINDLESS_SHADER: &[u8] = include_bytes!(env!("bindless_shader.spv"));
 
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 #[repr(C)]
@@ -15,6 +50,32 @@ struct UniformBuffer {
     screen_size: [f32; 2],
 }
 
+/// Mirrors `PushConstants` of the shader.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct PushConstants {
+    transform: [[f32; 4]; 4],
+    clip_rect: [f32; 4],
+    depth: f32,
+    texture_index: u32,
+}
+
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Where `UiPass::record` puts the paint jobs.
+#[derive(Clone, Copy)]
+enum Placement<'a> {
+    /// On the screen, at an optional depth per paint job.
+    Screen { depths: Option<&'a [f32]> },
+    /// In a 3D scene, through a transform from points to clip space.
+    World { transform: [[f32; 4]; 4] },
+}
+
 pub struct ScreenDescriptor {
     /// Width of the window in physical pixel.
     pub physical_width: u32,
@@ -22,57 +83,451 @@ pub struct ScreenDescriptor {
     pub physical_height: u32,
     /// HiDPI scale factor.
     pub scale_factor: f32,
+    /// Part of the target the UI is drawn into, in physical pixels. Both the viewport and the
+    /// clip rects are moved into it, and the clear color only covers it. `None` uses the whole
+    /// target. Multisampled passes resolve over the whole target and cannot draw into a viewport.
+    pub viewport: Option<vk::Rect2D>,
+}
+
+/// Describes the color attachment [`UiPass`] renders into.
+#[derive(Clone, Debug)]
+pub struct UiPassDescriptor {
+    /// Format of the target image.
+    pub format: vk::Format,
+    /// Layout the target image is in when [`UiPass::execute`] is recorded, `UNDEFINED` if its
+    /// contents can be discarded.
+    pub initial_layout: vk::ImageLayout,
+    /// Layout the target image is transitioned to at the end of the pass.
+    pub final_layout: vk::ImageLayout,
+    /// How the commands before and after the pass use the target image. Their depth tests and
+    /// writes are synchronized with as well when the pass has a depth attachment.
+    pub target_barriers: TargetBarriers,
+    /// `LOAD` to draw over the existing contents, `DONT_CARE` if they are fully cleared or covered.
+    pub load_op: vk::AttachmentLoadOp,
+    /// Number of frames the GPU may be working on at the same time. Buffers are kept per frame
+    /// so that [`UiPass::update_buffers`] never writes to memory an earlier frame still reads.
+    pub frames_in_flight: usize,
+    /// Colour encoding the fragment shader writes, must match `format`.
+    pub output_color_space: OutputColorSpace,
+    /// Draw missing or freed textures with a checkerboard instead of failing with an [`Error`].
+    pub substitute_missing_textures: bool,
+    /// Samples per pixel. Above `TYPE_1` the UI is drawn into a transient multisampled image that
+    /// is resolved into the target, replacing its contents, so `load_op` must not be `LOAD` and
+    /// `clear_color` must be passed to [`UiPass::execute`].
+    pub sample_count: vk::SampleCountFlags,
+    /// Depth attachment shared with the scene, drawn with [`UiPass::execute_with_depth`].
+    pub depth: Option<DepthDescriptor>,
+    /// Place the UI in a 3D scene with [`UiPass::execute_world`] instead of drawing it on the
+    /// screen. Clip rects are then applied in the fragment shader, since scissors cannot follow
+    /// the transform.
+    pub world_space: bool,
+    /// Clip paint jobs in the fragment shader and merge consecutive ones using the same texture
+    /// into a single draw call, instead of setting a scissor per paint job. Compare the two with
+    /// [`UiPassStats::draw_calls`].
+    pub batch_clip_rects: bool,
+    /// Keep every texture in one descriptor array of this many entries and select it with a
+    /// push constant, so the texture set is bound once per frame instead of once per paint job.
+    /// Paint jobs are still drawn one by one, draws using different textures are not merged.
+    /// The font and placeholder take two entries, and the entry of a freed user texture is
+    /// reused once the frames in flight are done with it. Needs the descriptor indexing features
+    /// and cannot be changed by [`UiPass::set_descriptor`].
+    pub bindless_textures: Option<u32>,
+    /// Measure the render pass with timestamp queries, reported in [`UiPassStats::gpu_time`].
+    /// Fails with [`Error::InvalidDescriptor`] if the graphics queue has no timestamp support.
+    pub gpu_timing: bool,
+}
+
+/// Describes the depth attachment UI drawn into a 3D scene is tested against.
+///
+/// The attachment is always loaded, so the UI is sorted against the scene geometry drawn before
+/// it. With multisampling it must have the same sample count as the pass.
+#[derive(Clone, Debug)]
+pub struct DepthDescriptor {
+    /// Format of the depth image.
+    pub format: vk::Format,
+    /// Layout the depth image is in when [`UiPass::execute_with_depth`] is recorded.
+    pub initial_layout: vk::ImageLayout,
+    /// Layout the depth image is transitioned to at the end of the pass.
+    pub final_layout: vk::ImageLayout,
+    /// Comparison against the scene depth, `NEVER` is not useful.
+    pub compare_op: vk::CompareOp,
+    /// Write the depth of the UI, so geometry drawn afterwards is hidden behind it.
+    pub write: bool,
+}
+
+impl Default for DepthDescriptor {
+    fn default() -> Self {
+        Self {
+            format: vk::Format::D32_SFLOAT,
+            initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            write: false,
+        }
+    }
+}
+
+impl UiPassDescriptor {
+    fn validate(&self) -> Result<()> {
+        if self.load_op == vk::AttachmentLoadOp::CLEAR {
+            return Err(Error::InvalidDescriptor(
+                "clear through the clear_color argument of UiPass::execute instead",
+            ));
+        }
+        if self.sample_count != vk::SampleCountFlags::TYPE_1
+            && self.load_op == vk::AttachmentLoadOp::LOAD
+        {
+            return Err(Error::InvalidDescriptor(
+                "multisampled passes resolve over the target and cannot load it",
+            ));
+        }
+        if self.batch_clip_rects && (self.world_space || self.depth.is_some()) {
+            return Err(Error::InvalidDescriptor(
+                "batched clip rects cannot be combined with world space or a depth attachment",
+            ));
+        }
+        if self.bindless_textures.is_some() && (self.world_space || self.batch_clip_rects) {
+            return Err(Error::InvalidDescriptor(
+                "bindless textures cannot be combined with world space or batched clip rects",
+            ));
+        }
+        if self
+            .bindless_textures
+            .map_or(false, |capacity| capacity < 2)
+        {
+            return Err(Error::InvalidDescriptor(
+                "bindless_textures needs room for the font and placeholder textures",
+            ));
+        }
+        if self.target_barriers.src.stage_mask.is_empty()
+            || self.target_barriers.dst.stage_mask.is_empty()
+        {
+            return Err(Error::InvalidDescriptor(
+                "target barriers need at least one stage on each side",
+            ));
+        }
+        if self.frames_in_flight == 0 {
+            return Err(Error::InvalidDescriptor(
+                "frames_in_flight must be at least one",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for UiPassDescriptor {
+    fn default() -> Self {
+        Self {
+            format: vk::Format::B8G8R8A8_UNORM,
+            initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            target_barriers: TargetBarriers::default(),
+            load_op: vk::AttachmentLoadOp::LOAD,
+            frames_in_flight: 2,
+            output_color_space: OutputColorSpace::Srgb,
+            substitute_missing_textures: false,
+            sample_count: vk::SampleCountFlags::TYPE_1,
+            depth: None,
+            world_space: false,
+            batch_clip_rects: false,
+            bindless_textures: None,
+            gpu_timing: false,
+        }
+    }
+}
+
+/// Colour encoding of the fragment shader output.
+///
+/// rust-gpu at the pinned revision has no specialization constant support, so each mode is a
+/// separate fragment entry point chosen when the pipeline is created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    /// Linear colour, for `*_SRGB` targets where the hardware does the sRGB encoding.
+    Linear,
+    /// Gamma-encoded sRGB, for `*_UNORM` targets.
+    Srgb,
+}
+
+impl OutputColorSpace {
+    /// Picks the output encoding matching a target format.
+    pub fn for_format(format: vk::Format) -> Self {
+        match format {
+            vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32 => Self::Linear,
+            // Float formats store linear values as well.
+            vk::Format::R16G16B16A16_SFLOAT
+            | vk::Format::R32G32B32A32_SFLOAT
+            | vk::Format::B10G11R11_UFLOAT_PACK32 => Self::Linear,
+            _ => Self::Srgb,
+        }
+    }
+}
+
+/// Source data for a user texture registered with [`UiPass::register_user_texture`].
+pub enum UserTexture {
+    /// Tightly packed RGBA8 pixels in sRGB space, staged by the next [`UiPass::update_buffers`]
+    /// and copied when that frame is recorded.
+    Rgba {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// An image owned by the caller. It must be in `SHADER_READ_ONLY_OPTIMAL` layout when drawn.
+    Image(maligog::Image),
+}
+
+impl UserTexture {
+    fn validate(&self) -> Result<()> {
+        match self {
+            UserTexture::Rgba {
+                width,
+                height,
+                pixels,
+            } if pixels.len() != (width * height * 4) as usize => {
+                Err(Error::InvalidTextureSize {
+                    width: *width,
+                    height: *height,
+                    len: pixels.len(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<maligog::Image> for UserTexture {
+    fn from(image: maligog::Image) -> Self {
+        Self::Image(image)
+    }
 }
 
 impl ScreenDescriptor {
+    fn validate(&self) -> Result<()> {
+        if self.physical_width == 0 || self.physical_height == 0 || !(self.scale_factor > 0.0) {
+            return Err(Error::InvalidScreenDescriptor);
+        }
+        if let Some(viewport) = self.viewport {
+            let fits = viewport.offset.x >= 0
+                && viewport.offset.y >= 0
+                && viewport.offset.x as u64 + viewport.extent.width as u64
+                    <= self.physical_width as u64
+                && viewport.offset.y as u64 + viewport.extent.height as u64
+                    <= self.physical_height as u64;
+            if viewport.extent.width == 0 || viewport.extent.height == 0 || !fits {
+                return Err(Error::InvalidScreenDescriptor);
+            }
+        }
+        Ok(())
+    }
+
+    /// The area of the target covered by the UI.
+    fn viewport_rect(&self) -> vk::Rect2D {
+        self.viewport.unwrap_or(vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: vk::Extent2D {
+                width: self.physical_width,
+                height: self.physical_height,
+            },
+        })
+    }
+
     fn logical_size(&self) -> (u32, u32) {
-        let logical_width = self.physical_width as f32 / self.scale_factor;
-        let logical_height = self.physical_height as f32 / self.scale_factor;
+        let extent = self.viewport_rect().extent;
+        let logical_width = extent.width as f32 / self.scale_factor;
+        let logical_height = extent.height as f32 / self.scale_factor;
         (logical_width as u32, logical_height as u32)
     }
 }
 
+/// Resources written by [`UiPass::update_buffers`] for a single frame in flight.
+struct FrameResources {
+    /// Indices of all paint jobs, rebased so they address `vertex_buffer` directly.
+    index_buffer: Option<maligog::Buffer>,
+    /// Vertices of all paint jobs, packed back to back.
+    vertex_buffer: Option<maligog::Buffer>,
+    /// Range of `index_buffer` used by each paint job.
+    draws: Vec<Draw>,
+    /// Number of paint jobs the draws were made from, which must be recorded with them.
+    paint_jobs: usize,
+    uniform_buffer: maligog::Buffer,
+    uniform_descriptor_set: maligog::DescriptorSet,
+    /// Texture uploads recorded with the frame, kept until the frame is reused.
+    uploads: Vec<PendingUpload>,
+}
+
+impl FrameResources {
+    fn new(device: &Device, uniform_descriptors: &mut DescriptorAllocator) -> Self {
+        let uniform_buffer = device.create_buffer(
+            Some("uniform buffer"),
+            std::mem::size_of::<UniformBuffer>(),
+            maligog::BufferUsageFlags::UNIFORM_BUFFER | maligog::BufferUsageFlags::TRANSFER_DST,
+            maligog::MemoryLocation::CpuToGpu,
+        );
+        let uniform_descriptor_set = uniform_descriptors.allocate(btreemap! {
+            0 => maligog::DescriptorUpdate::Buffer(vec![BufferView { buffer: uniform_buffer.clone(), offset: 0 }])
+        });
+        Self {
+            index_buffer: None,
+            vertex_buffer: None,
+            draws: Vec::with_capacity(64),
+            paint_jobs: 0,
+            uniform_buffer,
+            uniform_descriptor_set,
+            uploads: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Draw {
+    first_index: u32,
+    index_count: u32,
+    /// The first paint job drawn, which provides the texture and clip rect.
+    paint_job: usize,
+}
+
+/// The draw calls of a frame, worked out before anything is recorded.
+struct DrawPlan {
+    /// Descriptor set and bindless slot of the texture of every paint job.
+    textures: Vec<(maligog::DescriptorSet, u32)>,
+    /// Draws that cover any pixels, with their scissor.
+    draws: Vec<(Draw, vk::Rect2D)>,
+    /// Part of the target the UI is drawn into.
+    viewport: vk::Rect2D,
+    draw_viewport: vk::Viewport,
+}
+
+/// The shader modules the pipelines of a [`UiPass`] use.
+struct ShaderModules {
+    main: maligog::ShaderModule,
+    /// The fragment shaders of [`UiPassDescriptor::bindless_textures`]. They need descriptor
+    /// indexing, so the module is only created for passes using them.
+    bindless: Option<maligog::ShaderModule>,
+}
+
+impl ShaderModules {
+    fn new(device: &Device, descriptor: &UiPassDescriptor) -> Self {
+        Self {
+            main: device.create_shader_module(SHADER),
+            bindless: descriptor
+                .bindless_textures
+                .map(|_| device.create_shader_module(BINDLESS_SHADER)),
+        }
+    }
+}
+
+/// Pipeline for a render pass owned by the caller, see [`UiPass::set_external_render_pass`].
+struct ExternalPipeline {
+    render_pass: maligog::RenderPass,
+    graphics_pipeline: maligog::GraphicsPipeline,
+}
+
+/// Size of a vertex in the vertex buffer, batched vertices are followed by their clip rect.
+fn vertex_stride(batch_clip_rects: bool) -> u32 {
+    let stride = std::mem::size_of::<egui::paint::Vertex>() as u32;
+    if batch_clip_rects {
+        stride + std::mem::size_of::<[f32; 4]>() as u32
+    } else {
+        stride
+    }
+}
+
+/// Smallest buffer allocated for the packed vertices or indices.
+const MIN_MESH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Makes sure `buffer` can hold `size` bytes, growing it geometrically. Returns whether a new
+/// buffer was created.
+fn reserve_buffer(
+    device: &Device,
+    buffer: &mut Option<maligog::Buffer>,
+    name: &str,
+    size: usize,
+    usage: vk::BufferUsageFlags,
+) -> bool {
+    if buffer
+        .as_ref()
+        .map_or(false, |buffer| buffer.size() >= size)
+    {
+        return false;
+    }
+    let size = size.next_power_of_two().max(MIN_MESH_BUFFER_SIZE);
+    *buffer =
+        Some(device.create_buffer(Some(name), size, usage, maligog::MemoryLocation::CpuToGpu));
+    true
+}
+
 pub struct UiPass {
     device: Device,
     graphics_pipeline: maligog::GraphicsPipeline,
-    index_buffers: Vec<maligog::Buffer>,
-    vertex_buffers: Vec<maligog::Buffer>,
-    uniform_buffer: maligog::Buffer,
-    uniform_descriptor_set: maligog::DescriptorSet,
-    texture_descriptor_set_layout: maligog::DescriptorSetLayout,
+    samplers: SamplerCache,
+    frames: Vec<FrameResources>,
+    /// Incremented by every [`UiPass::update_buffers`], used to age retired descriptor sets.
+    frame_number: u64,
+    /// Texture sets that were replaced or freed, with the frame number they were last used in.
+    retired_texture_sets: Vec<(u64, maligog::DescriptorSet)>,
+    /// Scratch space the paint jobs are packed into before being copied to the GPU.
+    vertex_data: Vec<u8>,
+    index_data: Vec<u32>,
+    stats: UiPassStats,
+    /// Texture uploads since the last [`UiPass::update_buffers`].
+    texture_uploads: usize,
+    gpu_timer: Option<GpuTimer>,
+    font_atlas: Option<FontAtlas>,
     texture_descriptor_set: Option<maligog::DescriptorSet>,
     texture_version: Option<u64>,
     next_user_texture_id: u64,
-    pending_user_textures: Vec<(u64, egui::Texture)>,
+    pending_user_textures: Vec<(u64, UserTexture, SamplerDescriptor)>,
+    /// Texture copies to record before the next frame is drawn.
+    pending_uploads: Vec<PendingUpload>,
     user_textures: Vec<Option<maligog::DescriptorSet>>,
+    /// Checkerboard drawn in place of missing textures, see
+    /// [`UiPassDescriptor::substitute_missing_textures`].
+    placeholder_descriptor_set: Option<maligog::DescriptorSet>,
+    /// Missing textures already warned about, so the placeholder is not reported every frame.
+    reported_missing_textures: HashSet<egui::TextureId>,
+    /// Every texture in one array, see [`UiPassDescriptor::bindless_textures`].
+    bindless: Option<BindlessTextures>,
     render_pass: maligog::RenderPass,
-    descriptor_pool: maligog::DescriptorPool,
+    external: Option<ExternalPipeline>,
+    targets: TargetCache,
+    /// Target of [`UiPass::render_to_image`], created by its first call.
+    headless_target: Option<HeadlessTarget>,
+    pipeline_layout: maligog::PipelineLayout,
+    shader_modules: ShaderModules,
+    descriptor: UiPassDescriptor,
+    uniform_descriptors: DescriptorAllocator,
+    texture_descriptors: DescriptorAllocator,
 }
 
 impl UiPass {
-    pub fn new(device: &maligog::Device) -> Self {
-        let shader_module = device.create_shader_module(SHADER);
-        let uniform_buffer = device.create_buffer(
-            Some("uniform buffer"),
-            std::mem::size_of::<UniformBuffer>(),
-            maligog::BufferUsageFlags::UNIFORM_BUFFER | maligog::BufferUsageFlags::TRANSFER_DST,
-            maligog::MemoryLocation::CpuToGpu,
-        );
-        let sampler = device.create_sampler(
-            Some("egui sampler"),
-            maligog::Filter::NEAREST,
-            maligog::Filter::NEAREST,
-            maligog::SamplerAddressMode::REPEAT,
-            maligog::SamplerAddressMode::REPEAT,
-        );
+    pub fn new(device: &maligog::Device, descriptor: &UiPassDescriptor) -> Result<Self> {
+        descriptor.validate()?;
 
+        let shader_modules = ShaderModules::new(device, descriptor);
         let uniform_descriptor_set_layout = device.create_descriptor_set_layout(
             Some("uniform"),
+            &[maligog::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: maligog::DescriptorType::UniformBuffer,
+                stage_flags: maligog::ShaderStageFlags::VERTEX,
+                descriptor_count: 1,
+                variable_count: false,
+            }],
+        );
+
+        // Every texture carries its own sampler so filtering can differ per texture.
+        let texture_descriptor_set_layout = device.create_descriptor_set_layout(
+            Some("texture"),
             &[
                 maligog::DescriptorSetLayoutBinding {
                     binding: 0,
-                    descriptor_type: maligog::DescriptorType::UniformBuffer,
-                    stage_flags: maligog::ShaderStageFlags::VERTEX,
+                    descriptor_type: maligog::DescriptorType::SampledImage,
+                    stage_flags: maligog::ShaderStageFlags::FRAGMENT,
                     descriptor_count: 1,
                     variable_count: false,
                 },
@@ -86,409 +541,1308 @@ impl UiPass {
             ],
         );
 
-        let texture_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("texture"),
-            &[maligog::DescriptorSetLayoutBinding {
-                binding: 0,
-                descriptor_type: maligog::DescriptorType::SampledImage,
-                stage_flags: maligog::ShaderStageFlags::FRAGMENT,
-                descriptor_count: 1,
-                variable_count: false,
-            }],
-        );
+        let bindless = descriptor
+            .bindless_textures
+            .map(|capacity| BindlessTextures::new(device, capacity, descriptor.frames_in_flight));
 
         let pipeline_layout = device.create_pipeline_layout(
             Some("egui pipeline layout"),
             &[
                 &uniform_descriptor_set_layout,
-                &texture_descriptor_set_layout,
+                bindless
+                    .as_ref()
+                    .map_or(&texture_descriptor_set_layout, BindlessTextures::layout),
             ],
-            &[],
+            &[vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .size(std::mem::size_of::<PushConstants>() as u32)
+                .build()],
         );
 
-        let render_pass = device.create_render_pass(
-            &vk::RenderPassCreateInfo::builder()
-                .attachments(&[vk::AttachmentDescription::builder()
-                    .format(vk::Format::B8G8R8A8_UNORM)
-                    .samples(vk::SampleCountFlags::TYPE_1)
-                    .load_op(vk::AttachmentLoadOp::LOAD)
-                    .store_op(vk::AttachmentStoreOp::STORE)
-                    .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                    .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                    .build()])
-                .subpasses(&[vk::SubpassDescription::builder()
-                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                    .color_attachments(&[vk::AttachmentReference::builder()
-                        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                        .attachment(0)
-                        .build()])
-                    .build()])
-                .build(),
-        );
-
-        let graphics_pipeline = device.create_graphics_pipeline(
-            Some("egui pipeline"),
+        let render_pass = create_render_pass(device, descriptor);
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
             &pipeline_layout,
-            vec![
-                maligog::ShaderStage::new(
-                    &shader_module,
-                    maligog::ShaderStageFlags::VERTEX,
-                    "main_vs",
-                ),
-                maligog::ShaderStage::new(
-                    &shader_module,
-                    maligog::ShaderStageFlags::FRAGMENT,
-                    "main_fs",
-                ),
-            ],
+            &shader_modules,
             &render_pass,
-            &vk::PipelineVertexInputStateCreateInfo::builder()
-                .vertex_binding_descriptions(&[vk::VertexInputBindingDescription::builder()
-                    .stride(5 * 4)
-                    .input_rate(vk::VertexInputRate::VERTEX)
-                    .binding(0)
-                    .build()])
-                .vertex_attribute_descriptions(&[
-                    vk::VertexInputAttributeDescription::builder()
-                        .binding(0)
-                        .location(0)
-                        .format(vk::Format::R32G32_SFLOAT)
-                        .offset(0)
-                        .build(),
-                    vk::VertexInputAttributeDescription::builder()
-                        .binding(0)
-                        .location(1)
-                        .format(vk::Format::R32G32_SFLOAT)
-                        .offset(4 * 2)
-                        .build(),
-                    vk::VertexInputAttributeDescription::builder()
-                        .binding(0)
-                        .location(2)
-                        .format(vk::Format::R32_UINT)
-                        .offset(4 * 4)
-                        .build(),
-                ])
-                .build(),
-            &vk::PipelineInputAssemblyStateCreateInfo::builder()
-                .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-                .build(),
-            &vk::PipelineRasterizationStateCreateInfo::builder()
-                .cull_mode(vk::CullModeFlags::NONE)
-                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-                .polygon_mode(vk::PolygonMode::FILL)
-                .line_width(1.0)
-                .build(),
-            &vk::PipelineMultisampleStateCreateInfo::builder()
-                .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-                .build(),
-            &vk::PipelineDepthStencilStateCreateInfo::default(),
-            &vk::PipelineColorBlendStateCreateInfo::builder()
-                .attachments(&[vk::PipelineColorBlendAttachmentState::builder()
-                    .blend_enable(true)
-                    .color_blend_op(vk::BlendOp::ADD)
-                    .src_color_blend_factor(vk::BlendFactor::ONE)
-                    .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-                    .alpha_blend_op(vk::BlendOp::ADD)
-                    .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
-                    .dst_alpha_blend_factor(vk::BlendFactor::ONE)
-                    .color_write_mask(vk::ColorComponentFlags::all())
-                    .build()])
-                .build(),
-            &vk::PipelineViewportStateCreateInfo::builder()
-                .viewport_count(1)
-                .scissor_count(1),
-            &vk::PipelineDynamicStateCreateInfo::builder()
-                .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
-                .build(),
+            descriptor,
         );
 
-        let descriptor_pool = device.create_descriptor_pool(
+        let mut uniform_descriptors = DescriptorAllocator::new(
+            device,
+            "uniform descriptor set",
+            &uniform_descriptor_set_layout,
+            &[(vk::DescriptorType::UNIFORM_BUFFER, 1)],
+        );
+        let texture_descriptors = DescriptorAllocator::new(
+            device,
+            "texture descriptor set",
+            &texture_descriptor_set_layout,
             &[
-                vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
-                    .descriptor_count(1)
-                    .build(),
-                vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::SAMPLED_IMAGE)
-                    .descriptor_count(1)
-                    .build(),
+                (vk::DescriptorType::SAMPLED_IMAGE, 1),
+                (vk::DescriptorType::SAMPLER, 1),
             ],
-            2,
         );
 
-        let uniform_descriptor_set = device.create_descriptor_set(
-            Some("uniform descriptor set"),
-            &descriptor_pool,
-            &uniform_descriptor_set_layout,
-            btreemap! {
-                0 => maligog::DescriptorUpdate::Buffer(vec![BufferView { buffer: uniform_buffer.clone(), offset: 0 }]),
-                1 => maligog::DescriptorUpdate::Sampler(vec![sampler.clone()])
-            },
-        );
+        let frames = (0..descriptor.frames_in_flight)
+            .map(|_| FrameResources::new(device, &mut uniform_descriptors))
+            .collect();
 
-        Self {
+        let mut ui_pass = Self {
             device: device.clone(),
             graphics_pipeline,
-            index_buffers: Vec::with_capacity(64),
-            vertex_buffers: Vec::with_capacity(64),
-            uniform_buffer,
-            uniform_descriptor_set,
-            texture_descriptor_set_layout,
+            samplers: SamplerCache::new(device),
+            frames,
+            frame_number: 0,
+            retired_texture_sets: Vec::new(),
+            vertex_data: Vec::new(),
+            index_data: Vec::new(),
+            stats: UiPassStats::default(),
+            texture_uploads: 0,
+            gpu_timer: descriptor
+                .gpu_timing
+                .then(|| GpuTimer::new(device, descriptor.frames_in_flight))
+                .transpose()?,
+            font_atlas: None,
             texture_descriptor_set: None,
             texture_version: None,
             next_user_texture_id: 0,
             pending_user_textures: Vec::new(),
+            pending_uploads: Vec::new(),
             user_textures: Vec::new(),
+            placeholder_descriptor_set: None,
+            reported_missing_textures: HashSet::new(),
+            bindless,
             render_pass,
-            descriptor_pool,
+            external: None,
+            targets: TargetCache::new(),
+            headless_target: None,
+            pipeline_layout,
+            shader_modules,
+            descriptor: descriptor.clone(),
+            uniform_descriptors,
+            texture_descriptors,
+        };
+        ui_pass.update_placeholder();
+        Ok(ui_pass)
+    }
+
+    /// Drops the cached image views and framebuffers of all render targets.
+    ///
+    /// Call this when swapchain images are recreated, e.g. on resize, after the GPU has finished
+    /// every frame that rendered into them.
+    pub fn invalidate_targets(&mut self) {
+        self.targets.clear();
+    }
+
+    pub fn descriptor(&self) -> &UiPassDescriptor {
+        &self.descriptor
+    }
+
+    /// Recreates the render pass and pipeline for a new target description.
+    ///
+    /// The GPU must be idle when the number of frames in flight changes.
+    pub fn set_descriptor(&mut self, descriptor: &UiPassDescriptor) -> Result<()> {
+        descriptor.validate()?;
+        if descriptor.bindless_textures != self.descriptor.bindless_textures {
+            return Err(Error::InvalidDescriptor(
+                "bindless_textures can only be chosen when the UiPass is created",
+            ));
+        }
+        // Before anything else changes, since the queue may not support timestamps.
+        if !descriptor.gpu_timing {
+            self.gpu_timer = None;
+        } else if self.gpu_timer.is_none() {
+            self.gpu_timer = Some(GpuTimer::new(&self.device, descriptor.frames_in_flight)?);
+        }
+        if descriptor.frames_in_flight != self.frames.len() {
+            for frame in self.frames.drain(..) {
+                self.uniform_descriptors.free(frame.uniform_descriptor_set);
+            }
+            for _ in 0..descriptor.frames_in_flight {
+                let frame = FrameResources::new(&self.device, &mut self.uniform_descriptors);
+                self.frames.push(frame);
+            }
+            if let Some(bindless) = &mut self.bindless {
+                bindless.set_frames_in_flight(descriptor.frames_in_flight);
+            }
+            if let Some(gpu_timer) = &mut self.gpu_timer {
+                gpu_timer.set_frames_in_flight(descriptor.frames_in_flight);
+            }
+        }
+        // Cached framebuffers belong to the old render pass.
+        self.targets.clear();
+        self.render_pass = create_render_pass(&self.device, descriptor);
+        self.graphics_pipeline = create_graphics_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &self.shader_modules,
+            &self.render_pass,
+            descriptor,
+        );
+        if let Some(external) = &mut self.external {
+            external.graphics_pipeline = create_graphics_pipeline(
+                &self.device,
+                &self.pipeline_layout,
+                &self.shader_modules,
+                &external.render_pass,
+                descriptor,
+            );
+        }
+        self.descriptor = descriptor.clone();
+        self.update_placeholder();
+        Ok(())
+    }
+
+    /// Creates the pipeline [`UiPass::record_draws`] uses to draw into the first subpass of a
+    /// render pass owned by the caller, e.g. after the scene geometry drawn in the same subpass.
+    ///
+    /// maligog creates pipelines for the first subpass of a render pass only, so later subpasses
+    /// cannot be drawn into. The subpass must have one color attachment with the `format` and
+    /// `sample_count` of the [`UiPassDescriptor`], and a depth attachment if it has a
+    /// [`DepthDescriptor`]. The layouts and load op of the descriptor are not used for it. The
+    /// pipeline is recreated by [`UiPass::set_descriptor`].
+    pub fn set_external_render_pass(&mut self, render_pass: &maligog::RenderPass) {
+        self.external = Some(ExternalPipeline {
+            render_pass: render_pass.clone(),
+            graphics_pipeline: create_graphics_pipeline(
+                &self.device,
+                &self.pipeline_layout,
+                &self.shader_modules,
+                render_pass,
+                &self.descriptor,
+            ),
+        });
+    }
+
+    /// Creates the checkerboard texture the first time it is enabled.
+    fn update_placeholder(&mut self) {
+        if !self.descriptor.substitute_missing_textures || self.placeholder_descriptor_set.is_some()
+        {
+            return;
+        }
+        const SIZE: u32 = 8;
+        let pixels: Vec<u8> = (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let (x, y) = (i % SIZE, i / SIZE);
+                if (x / 4 + y / 4) % 2 == 0 {
+                    [255, 0, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                }
+            })
+            .collect();
+        let image = self.upload_rgba(
+            "egui placeholder texture",
+            vk::Format::R8G8B8A8_SRGB,
+            SIZE,
+            SIZE,
+            &pixels,
+        );
+        let sampler = self.samplers.get(SamplerDescriptor::NEAREST_REPEAT);
+        if let Some(bindless) = &mut self.bindless {
+            bindless.set(
+                bindless::PLACEHOLDER_SLOT,
+                image.create_view(),
+                sampler.clone(),
+            );
         }
+        self.placeholder_descriptor_set = Some(
+            self.texture_descriptors
+                .allocate(texture_descriptor_update(image.create_view(), &sampler)),
+        );
     }
 
+    /// Records the draws uploaded by the [`UiPass::update_buffers`] call with the same `frame_index`.
     pub fn execute(
         &mut self,
+        frame_index: usize,
         recorder: &mut maligog::CommandRecorder,
         color_attachment: &maligog::Image,
         paint_jobs: &[egui::paint::ClippedMesh],
         screen_descriptor: &ScreenDescriptor,
         clear_color: Option<vk::ClearColorValue>,
-    ) {
-        let image_view = color_attachment.create_view();
-        let framebuffer = self.device.create_framebuffer(
-            self.render_pass.clone(),
+    ) -> Result<UiPassStats> {
+        if self.descriptor.depth.is_some() {
+            return Err(Error::InvalidDescriptor(
+                "passes with a depth attachment are recorded with UiPass::execute_with_depth",
+            ));
+        }
+        if self.descriptor.world_space {
+            return Err(Error::InvalidDescriptor(
+                "world space passes are recorded with UiPass::execute_world",
+            ));
+        }
+        self.record(
+            frame_index,
+            recorder,
+            color_attachment,
+            None,
+            paint_jobs,
+            Placement::Screen { depths: None },
+            screen_descriptor,
+            clear_color,
+        )
+    }
+
+    /// Like [`UiPass::execute`], but tests the UI against `depth_attachment`.
+    ///
+    /// `depths` holds the depth of every paint job in `0.0..=1.0`, so each window can be placed
+    /// in the scene on its own.
+    pub fn execute_with_depth(
+        &mut self,
+        frame_index: usize,
+        recorder: &mut maligog::CommandRecorder,
+        color_attachment: &maligog::Image,
+        depth_attachment: &maligog::Image,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        depths: &[f32],
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> Result<UiPassStats> {
+        if self.descriptor.depth.is_none() {
+            return Err(Error::InvalidDescriptor(
+                "execute_with_depth needs a pass created with a DepthDescriptor",
+            ));
+        }
+        if self.descriptor.world_space {
+            return Err(Error::InvalidDescriptor(
+                "world space passes are recorded with UiPass::execute_world",
+            ));
+        }
+        if depths.len() != paint_jobs.len() {
+            return Err(Error::DepthCountMismatch {
+                paint_jobs: paint_jobs.len(),
+                depths: depths.len(),
+            });
+        }
+        self.record(
+            frame_index,
+            recorder,
+            color_attachment,
+            Some(depth_attachment),
+            paint_jobs,
+            Placement::Screen {
+                depths: Some(depths),
+            },
+            screen_descriptor,
+            clear_color,
+        )
+    }
+
+    /// Draws the UI into a 3D scene, e.g. onto an in-game terminal.
+    ///
+    /// `transform` is a column-major matrix from egui points to clip space, and the viewport is
+    /// not flipped, so it follows the same conventions as the scene's own projection.
+    /// `depth_attachment` must be given if and only if the pass has a [`DepthDescriptor`].
+    pub fn execute_world(
+        &mut self,
+        frame_index: usize,
+        recorder: &mut maligog::CommandRecorder,
+        color_attachment: &maligog::Image,
+        depth_attachment: Option<&maligog::Image>,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        transform: [[f32; 4]; 4],
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> Result<UiPassStats> {
+        if !self.descriptor.world_space {
+            return Err(Error::InvalidDescriptor(
+                "execute_world needs a pass created with world_space",
+            ));
+        }
+        if self.descriptor.depth.is_some() != depth_attachment.is_some() {
+            return Err(Error::InvalidDescriptor(
+                "a depth attachment must be passed exactly when the pass has a DepthDescriptor",
+            ));
+        }
+        self.record(
+            frame_index,
+            recorder,
+            color_attachment,
+            depth_attachment,
+            paint_jobs,
+            Placement::World { transform },
+            screen_descriptor,
+            clear_color,
+        )
+    }
+
+    /// Records the draws uploaded by [`UiPass::update_buffers`] into a render pass the caller
+    /// has already begun, in its first subpass, see [`UiPass::set_external_render_pass`].
+    ///
+    /// Only the pipeline, descriptor sets, buffers, scissors and viewport are set and nothing is
+    /// cleared. Staged textures must have been copied with [`UiPass::record_uploads`] before the
+    /// render pass began. `depths` must be given exactly when the pass has a [`DepthDescriptor`],
+    /// see [`UiPass::execute_with_depth`]. [`UiPassStats::gpu_time`] is not measured, since
+    /// queries cannot be reset inside a render pass.
+    pub fn record_draws(
+        &mut self,
+        frame_index: usize,
+        recorder: &mut maligog::CommandRecorder,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        depths: Option<&[f32]>,
+        screen_descriptor: &ScreenDescriptor,
+    ) -> Result<UiPassStats> {
+        if self.external.is_none() {
+            return Err(Error::InvalidDescriptor(
+                "record_draws needs a render pass set with UiPass::set_external_render_pass",
+            ));
+        }
+        if !self.pending_uploads.is_empty() {
+            return Err(Error::InvalidDescriptor(
+                "textures must be uploaded with UiPass::record_uploads before the render pass",
+            ));
+        }
+        if self.descriptor.world_space {
+            return Err(Error::InvalidDescriptor(
+                "world space passes are recorded with UiPass::execute_world",
+            ));
+        }
+        if self.descriptor.depth.is_some() != depths.is_some() {
+            return Err(Error::InvalidDescriptor(
+                "depths must be passed exactly when the pass has a DepthDescriptor",
+            ));
+        }
+        if let Some(depths) = depths {
+            if depths.len() != paint_jobs.len() {
+                return Err(Error::DepthCountMismatch {
+                    paint_jobs: paint_jobs.len(),
+                    depths: depths.len(),
+                });
+            }
+        }
+        let placement = Placement::Screen { depths };
+        let plan = self.plan_draws(frame_index, paint_jobs, placement, screen_descriptor)?;
+        let external = self.external.as_ref().unwrap();
+        self.record_draw_calls(
+            frame_index,
+            recorder,
+            &external.graphics_pipeline,
+            paint_jobs,
+            placement,
+            &plan,
+        );
+        Ok(self.stats)
+    }
+
+    fn record(
+        &mut self,
+        frame_index: usize,
+        recorder: &mut maligog::CommandRecorder,
+        color_attachment: &maligog::Image,
+        depth_attachment: Option<&maligog::Image>,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        placement: Placement,
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> Result<UiPassStats> {
+        // The multisampled image starts out undefined and is resolved over the whole target.
+        if self.descriptor.sample_count != vk::SampleCountFlags::TYPE_1 {
+            if clear_color.is_none() {
+                return Err(Error::InvalidDescriptor(
+                    "multisampled passes replace the target and need a clear color",
+                ));
+            }
+            if screen_descriptor.viewport.is_some() {
+                return Err(Error::InvalidDescriptor(
+                    "multisampled passes replace the whole target and cannot use a viewport",
+                ));
+            }
+        }
+        let plan = self.plan_draws(frame_index, paint_jobs, placement, screen_descriptor)?;
+        self.record_uploads(frame_index, recorder);
+
+        self.targets.begin_frame(self.frames.len());
+        let framebuffer = self.targets.framebuffer(
+            &self.device,
+            &self.render_pass,
+            &self.descriptor,
+            color_attachment,
+            depth_attachment,
             screen_descriptor.physical_width,
             screen_descriptor.physical_height,
-            vec![&image_view],
         );
 
-        let scale_factor = screen_descriptor.scale_factor;
-        let physical_width = screen_descriptor.physical_width;
-        let physical_height = screen_descriptor.physical_height;
-
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin(recorder, frame_index % self.frames.len());
+        }
         recorder.begin_render_pass(&self.render_pass, &framebuffer, |recorder| {
             if let Some(color) = clear_color {
-                recorder.clear_attachments(
-                    &[vk::ClearAttachment::builder()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .color_attachment(0)
-                        .clear_value(vk::ClearValue { color })
-                        .build()],
-                    &[vk::ClearRect::builder()
-                        .base_array_layer(0)
-                        .layer_count(1)
-                        .rect(
-                            vk::Rect2D::builder()
-                                .offset(vk::Offset2D::default())
-                                .extent(
-                                    vk::Extent2D::builder()
-                                        .width(color_attachment.width())
-                                        .height(color_attachment.height())
-                                        .build(),
-                                )
-                                .build(),
-                        )
-                        .build()],
-                )
+                clear_viewport(recorder, color, plan.viewport);
             }
-            recorder.bind_graphics_pipeline(&self.graphics_pipeline, |recorder| {
-                recorder.bind_descriptor_sets(vec![&self.uniform_descriptor_set], 0);
-                for ((egui::ClippedMesh(clip_rect, mesh), vertex_buffer), index_buffer) in
-                    paint_jobs
-                        .iter()
-                        .zip(self.vertex_buffers.iter())
-                        .zip(self.index_buffers.iter())
-                {
-                    // Transform clip rect to physical pixels.
-                    let clip_min_x = scale_factor * clip_rect.min.x;
-                    let clip_min_y = scale_factor * clip_rect.min.y;
-                    let clip_max_x = scale_factor * clip_rect.max.x;
-                    let clip_max_y = scale_factor * clip_rect.max.y;
-
-                    // Make sure clip rect can fit within an `u32`.
-                    let clip_min_x = clip_min_x.clamp(0.0, physical_width as f32);
-                    let clip_min_y = clip_min_y.clamp(0.0, physical_height as f32);
-                    let clip_max_x = clip_max_x.clamp(clip_min_x, physical_width as f32);
-                    let clip_max_y = clip_max_y.clamp(clip_min_y, physical_height as f32);
-
-                    let clip_min_x = clip_min_x.round() as u32;
-                    let clip_min_y = clip_min_y.round() as u32;
-                    let clip_max_x = clip_max_x.round() as u32;
-                    let clip_max_y = clip_max_y.round() as u32;
-
-                    let width = (clip_max_x - clip_min_x).max(1);
-                    let height = (clip_max_y - clip_min_y).max(1);
-
-                    {
-                        // clip scissor rectangle to target size
-                        let x = clip_min_x.min(physical_width);
-                        let y = clip_min_y.min(physical_height);
-                        let width = width.min(physical_width - x);
-                        let height = height.min(physical_height - y);
-
-                        // skip rendering with zero-sized clip areas
-                        if width == 0 || height == 0 {
-                            continue;
-                        }
-
-                        recorder.set_scissor(&[vk::Rect2D {
-                            offset: vk::Offset2D {
-                                x: x as i32,
-                                y: y as i32,
-                            },
-                            extent: vk::Extent2D { width, height },
-                        }]);
-                        recorder.set_viewport(vk::Viewport {
-                            x: 0.0,
-                            y: physical_height as f32,
-                            width: physical_width as f32,
-                            height: -(physical_height as f32),
-                            min_depth: 0.1,
-                            max_depth: 1.0,
-                        });
+            self.record_draw_calls(
+                frame_index,
+                recorder,
+                &self.graphics_pipeline,
+                paint_jobs,
+                placement,
+                &plan,
+            );
+        });
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end(recorder, frame_index % self.frames.len());
+        }
+        Ok(self.stats)
+    }
+
+    /// Resolves the textures of `paint_jobs` and picks the scissor of every draw, so nothing is
+    /// recorded if a texture is missing.
+    fn plan_draws(
+        &mut self,
+        frame_index: usize,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        placement: Placement,
+        screen_descriptor: &ScreenDescriptor,
+    ) -> Result<DrawPlan> {
+        // Draws index into the paint jobs they were uploaded from.
+        let uploaded = self.frames[frame_index % self.frames.len()].paint_jobs;
+        if paint_jobs.len() != uploaded {
+            return Err(Error::PaintJobCountMismatch {
+                uploaded,
+                recorded: paint_jobs.len(),
+            });
+        }
+        let textures = paint_jobs
+            .iter()
+            .map(|egui::ClippedMesh(_, mesh)| self.resolve_texture(mesh.texture_id))
+            .collect::<Result<Vec<_>>>()?;
+
+        let viewport = screen_descriptor.viewport_rect();
+        let draw_viewport = match placement {
+            // `main_vs` outputs y pointing up, so the viewport is flipped.
+            Placement::Screen { .. } => {
+                vk::Viewport {
+                    x: viewport.offset.x as f32,
+                    y: (viewport.offset.y as u32 + viewport.extent.height) as f32,
+                    width: viewport.extent.width as f32,
+                    height: -(viewport.extent.height as f32),
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }
+            }
+            Placement::World { .. } => {
+                vk::Viewport {
+                    x: viewport.offset.x as f32,
+                    y: viewport.offset.y as f32,
+                    width: viewport.extent.width as f32,
+                    height: viewport.extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }
+            }
+        };
+
+        // Skip draws with zero-sized clip areas. Batched and world space draws are clipped in
+        // the fragment shader instead.
+        let frame = &self.frames[frame_index % self.frames.len()];
+        let clip_in_shader =
+            self.descriptor.batch_clip_rects || matches!(placement, Placement::World { .. });
+        let draws = frame
+            .draws
+            .iter()
+            .filter_map(|&draw| {
+                if clip_in_shader {
+                    return Some((draw, viewport));
+                }
+                let egui::ClippedMesh(clip_rect, _) = &paint_jobs[draw.paint_job];
+                scissor_rect(clip_rect, screen_descriptor).map(|scissor| (draw, scissor))
+            })
+            .collect::<Vec<_>>();
+        self.stats.draw_calls = draws.len();
+        if !self.descriptor.batch_clip_rects {
+            self.stats.skipped_clips = frame.draws.len() - draws.len();
+        }
+
+        Ok(DrawPlan {
+            textures,
+            draws,
+            viewport,
+            draw_viewport,
+        })
+    }
+
+    /// Binds `graphics_pipeline` and records the draws of `plan`, inside a render pass.
+    fn record_draw_calls(
+        &self,
+        frame_index: usize,
+        recorder: &mut maligog::CommandRecorder,
+        graphics_pipeline: &maligog::GraphicsPipeline,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        placement: Placement,
+        plan: &DrawPlan,
+    ) {
+        let frame = &self.frames[frame_index % self.frames.len()];
+        recorder.bind_graphics_pipeline(graphics_pipeline, |recorder| {
+            let (vertex_buffer, index_buffer) = match (&frame.vertex_buffer, &frame.index_buffer) {
+                (Some(vertex_buffer), Some(index_buffer)) => (vertex_buffer, index_buffer),
+                _ => return,
+            };
+            recorder.bind_descriptor_sets(vec![&frame.uniform_descriptor_set], 0);
+            recorder.bind_vertex_buffers(&[vertex_buffer], &[0]);
+            if let Some(bindless) = &self.bindless {
+                recorder.bind_descriptor_sets(
+                    vec![bindless.descriptor_set(frame_index % self.frames.len())],
+                    1,
+                );
+            }
+            for &(draw, scissor) in &plan.draws {
+                let (texture_descriptor_set, texture_index) = &plan.textures[draw.paint_job];
+                let egui::ClippedMesh(clip_rect, _) = &paint_jobs[draw.paint_job];
+                let (transform, depth) = match placement {
+                    Placement::Screen { depths } => {
+                        (
+                            IDENTITY,
+                            depths.map_or(0.0, |depths| depths[draw.paint_job]),
+                        )
                     }
-                    recorder.bind_descriptor_sets(
-                        vec![&self.get_texture_descriptor_set(mesh.texture_id)],
-                        1,
-                    );
-
-                    recorder.bind_index_buffer(&index_buffer, 0, vk::IndexType::UINT32);
-                    recorder.bind_vertex_buffers(&[&vertex_buffer], &[0]);
-                    recorder.draw_indexed(mesh.indices.len() as u32, 1);
+                    Placement::World { transform } => (transform, 0.0),
+                };
+                recorder.set_scissor(&[scissor]);
+                recorder.set_viewport(plan.draw_viewport);
+                if self.bindless.is_none() {
+                    recorder.bind_descriptor_sets(vec![texture_descriptor_set], 1);
                 }
-            });
+                recorder.push_constants(
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    bytemuck::bytes_of(&PushConstants {
+                        transform,
+                        clip_rect: [
+                            clip_rect.min.x,
+                            clip_rect.min.y,
+                            clip_rect.max.x,
+                            clip_rect.max.y,
+                        ],
+                        depth,
+                        texture_index: *texture_index,
+                    }),
+                );
+
+                recorder.bind_index_buffer(
+                    index_buffer,
+                    draw.first_index as u64 * std::mem::size_of::<u32>() as u64,
+                    vk::IndexType::UINT32,
+                );
+                recorder.draw_indexed(draw.index_count, 1);
+            }
         });
     }
 
-    fn get_texture_descriptor_set(&self, texture_id: egui::TextureId) -> maligog::DescriptorSet {
-        match texture_id {
+    /// Counters of the last frame, also returned by [`UiPass::update_buffers`] and
+    /// [`UiPass::execute`].
+    pub fn stats(&self) -> UiPassStats {
+        self.stats
+    }
+
+    /// Returns the descriptor set and bindless slot of a texture.
+    fn resolve_texture(
+        &mut self,
+        texture_id: egui::TextureId,
+    ) -> Result<(maligog::DescriptorSet, u32)> {
+        let texture = match texture_id {
             egui::TextureId::Egui => {
                 self.texture_descriptor_set
-                    .as_ref()
-                    .expect("egui texture was not set before the first draw")
                     .clone()
+                    .map(|descriptor_set| (descriptor_set, bindless::FONT_SLOT))
+                    .ok_or(Error::MissingFontTexture)
             }
             egui::TextureId::User(id) => {
-                let id = id as usize;
-                assert!(id < self.user_textures.len());
-                self.user_textures
-                    .get(id)
-                    .unwrap_or_else(|| panic!("user texture {} not found", id))
-                    .as_ref()
-                    .unwrap_or_else(|| panic!("user texture {} freed", id))
-                    .clone()
+                match self.user_textures.get(id as usize) {
+                    Some(Some(descriptor_set)) => {
+                        // Registered textures keep their slot until they are freed.
+                        let slot = match &self.bindless {
+                            Some(bindless) => bindless.user_slot(id).unwrap(),
+                            None => 0,
+                        };
+                        Ok((descriptor_set.clone(), slot))
+                    }
+                    Some(None) => Err(Error::FreedUserTexture(id)),
+                    None => Err(Error::UnknownUserTexture(id)),
+                }
             }
+        };
+        match (texture, &self.placeholder_descriptor_set) {
+            (Err(err), Some(placeholder)) => {
+                if self.reported_missing_textures.insert(texture_id) {
+                    log::warn!("{}, drawing a placeholder", err);
+                }
+                Ok((placeholder.clone(), bindless::PLACEHOLDER_SLOT))
+            }
+            (texture, _) => texture,
         }
     }
 
-    pub fn update_texture(&mut self, egui_texture: &egui::Texture) {
+    pub fn update_texture(&mut self, egui_texture: &egui::Texture) -> Result<()> {
         // Don't update the texture if it hasn't changed.
         if self.texture_version == Some(egui_texture.version) {
-            return;
+            return Ok(());
         }
-        // we need to convert the texture into rgba format
-        let egui_texture = egui::Texture {
-            version: egui_texture.version,
-            width: egui_texture.width,
-            height: egui_texture.height,
-            pixels: egui_texture
-                .pixels
-                .iter()
-                .flat_map(|p| std::iter::repeat(*p).take(4))
-                .collect(),
-        };
-        let descriptor_set = self.egui_texture_to_gpu(&egui_texture);
+        if egui_texture.pixels.len() != egui_texture.width * egui_texture.height {
+            return Err(Error::InvalidTextureSize {
+                width: egui_texture.width as u32,
+                height: egui_texture.height as u32,
+                len: egui_texture.pixels.len(),
+            });
+        }
+        // Glyphs added at runtime only touch a few rows, so the atlas is updated in place as long
+        // as its size stays the same.
+        if let Some(atlas) = self
+            .font_atlas
+            .as_mut()
+            .filter(|atlas| atlas.fits(egui_texture))
+        {
+            if let Some(upload) = atlas.update(&self.device, egui_texture) {
+                self.pending_uploads.push(upload);
+                self.texture_uploads += 1;
+            }
+            self.texture_version = Some(egui_texture.version);
+            return Ok(());
+        }
+
+        let (atlas, upload) = FontAtlas::new(&self.device, egui_texture);
+        self.pending_uploads.push(upload);
+        let sampler = self.samplers.get(SamplerDescriptor::default());
+        if let Some(bindless) = &mut self.bindless {
+            bindless.set(bindless::FONT_SLOT, atlas.create_view(), sampler.clone());
+        }
+        // The old set may still be bound by a frame in flight, so it is retired rather than
+        // rewritten; the allocator hands it out again once those frames are done.
+        let descriptor_set = self
+            .texture_descriptors
+            .allocate(texture_descriptor_update(atlas.create_view(), &sampler));
+        if let Some(old) = self.texture_descriptor_set.replace(descriptor_set) {
+            self.retire_texture_set(old);
+        }
+        self.font_atlas = Some(atlas);
+        self.texture_uploads += 1;
+        self.reported_missing_textures
+            .remove(&egui::TextureId::Egui);
 
         self.texture_version = Some(egui_texture.version);
-        self.texture_descriptor_set = Some(descriptor_set);
+        Ok(())
     }
 
-    fn egui_texture_to_gpu(&mut self, egui_texture: &egui::Texture) -> DescriptorSet {
-        let image = self.device.create_image_init(
-            Some("egui texture"),
-            vk::Format::B8G8R8A8_UNORM,
-            egui_texture.width as u32,
-            egui_texture.height as u32,
-            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
-            maligog::MemoryLocation::GpuOnly,
-            egui_texture.pixels.as_slice(),
-        );
+    /// Registers a user texture and returns the id to reference it from egui.
+    ///
+    /// Pixel data is staged by the next call to [`UiPass::update_buffers`] and copied when that
+    /// frame is recorded. `sampler` selects how the texture is filtered and addressed.
+    pub fn register_user_texture(
+        &mut self,
+        texture: impl Into<UserTexture>,
+        sampler: SamplerDescriptor,
+    ) -> Result<egui::TextureId> {
+        let texture = texture.into();
+        texture.validate()?;
+        let id = self.next_user_texture_id;
+        if let Some(bindless) = &mut self.bindless {
+            bindless.allocate_user_slot(id)?;
+        }
+        self.next_user_texture_id += 1;
+        self.user_textures.push(None);
+        self.pending_user_textures.push((id, texture, sampler));
+        Ok(egui::TextureId::User(id))
+    }
 
-        image.set_layout(
-            maligog::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::READ_ONLY_OPTIMAL_KHR,
-        );
+    /// Replaces the contents and sampler of a previously registered user texture.
+    pub fn update_user_texture(
+        &mut self,
+        id: egui::TextureId,
+        texture: impl Into<UserTexture>,
+        sampler: SamplerDescriptor,
+    ) -> Result<()> {
+        let id = match id {
+            egui::TextureId::User(id) => id,
+            egui::TextureId::Egui => return Err(Error::NotAUserTexture),
+        };
+        if id as usize >= self.user_textures.len() {
+            return Err(Error::UnknownUserTexture(id));
+        }
+        let texture = texture.into();
+        texture.validate()?;
+        self.pending_user_textures
+            .retain(|(pending, _, _)| *pending != id);
+        self.pending_user_textures.push((id, texture, sampler));
+        Ok(())
+    }
 
-        let descriptor_set = self.device.create_descriptor_set(
-            Some("texture descriptor set"),
-            &self.descriptor_pool,
-            &self.texture_descriptor_set_layout,
-            btreemap! {
-                0 => maligog::DescriptorUpdate::Image(vec![image.create_view()])
-            },
+    /// Frees a user texture. The id must not be drawn afterwards.
+    pub fn free_user_texture(&mut self, id: egui::TextureId) {
+        if let egui::TextureId::User(id) = id {
+            self.pending_user_textures
+                .retain(|(pending, _, _)| *pending != id);
+            if let Some(descriptor_set) = self
+                .user_textures
+                .get_mut(id as usize)
+                .and_then(Option::take)
+            {
+                self.retire_texture_set(descriptor_set);
+            }
+            if let Some(bindless) = &mut self.bindless {
+                bindless.free_user_slot(id, self.frame_number);
+            }
+        }
+    }
+
+    fn retire_texture_set(&mut self, descriptor_set: maligog::DescriptorSet) {
+        self.retired_texture_sets
+            .push((self.frame_number, descriptor_set));
+    }
+
+    /// Returns retired texture sets to the allocator, and freed bindless slots to the free list,
+    /// once no frame in flight can reference them.
+    fn recycle_texture_sets(&mut self) {
+        let frames_in_flight = self.frames.len() as u64;
+        let frame_number = self.frame_number;
+        let (done, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.retired_texture_sets)
+            .into_iter()
+            .partition(|(retired, _)| frame_number >= retired + frames_in_flight);
+        self.retired_texture_sets = pending;
+        for (_, descriptor_set) in done {
+            self.texture_descriptors.free(descriptor_set);
+        }
+        if let Some(bindless) = &mut self.bindless {
+            bindless.recycle_slots(frame_number, frames_in_flight);
+        }
+    }
+
+    fn upload_user_textures(&mut self) {
+        for (id, texture, sampler) in std::mem::take(&mut self.pending_user_textures) {
+            self.texture_uploads += 1;
+            let image = match texture {
+                UserTexture::Rgba {
+                    width,
+                    height,
+                    pixels,
+                } => {
+                    self.upload_rgba(
+                        "egui user texture",
+                        vk::Format::R8G8B8A8_SRGB,
+                        width,
+                        height,
+                        &pixels,
+                    )
+                }
+                UserTexture::Image(image) => image,
+            };
+            let sampler = self.samplers.get(sampler);
+            if let Some(bindless) = &mut self.bindless {
+                // Taken when the texture was registered.
+                let slot = bindless.user_slot(id).unwrap();
+                bindless.set(slot, image.create_view(), sampler.clone());
+            }
+            let descriptor_set = self
+                .texture_descriptors
+                .allocate(texture_descriptor_update(image.create_view(), &sampler));
+            if let Some(old) = self.user_textures[id as usize].replace(descriptor_set) {
+                self.retire_texture_set(old);
+            }
+            // Warn again if it goes missing later.
+            self.reported_missing_textures
+                .remove(&egui::TextureId::User(id));
+        }
+    }
+
+    /// Creates an image whose pixels are copied when the next frame is recorded.
+    fn upload_rgba(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> maligog::Image {
+        let image = self.device.create_image(
+            Some(name),
+            format,
+            width,
+            height,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            maligog::MemoryLocation::GpuOnly,
         );
+        self.pending_uploads.push(PendingUpload::new(
+            &self.device,
+            &image,
+            vk::ImageLayout::UNDEFINED,
+            pixels,
+            0,
+            width,
+            height,
+        ));
+        image
+    }
 
-        descriptor_set
+    /// Records the texture copies staged since the last frame, which [`UiPass::execute`] and its
+    /// variants do before drawing. Call it before beginning the render pass
+    /// [`UiPass::record_draws`] draws into.
+    pub fn record_uploads(&mut self, frame_index: usize, recorder: &mut maligog::CommandRecorder) {
+        let frame_count = self.frames.len();
+        let frame = &mut self.frames[frame_index % frame_count];
+        for upload in self.pending_uploads.drain(..) {
+            upload.record(recorder);
+            frame.uploads.push(upload);
+        }
     }
 
+    /// Uploads the paint jobs into the buffers of `frame_index`.
+    ///
+    /// The caller must make sure the GPU has finished the last frame submitted with the same
+    /// `frame_index` (modulo `frames_in_flight`), e.g. by waiting on that frame's fence.
     pub fn update_buffers(
         &mut self,
+        frame_index: usize,
         paint_jobs: &[egui::paint::ClippedMesh],
         screen_descriptor: &ScreenDescriptor,
-    ) {
-        let index_size = self.index_buffers.len();
-        let vertex_size = self.vertex_buffers.len();
+    ) -> Result<UiPassStats> {
+        screen_descriptor.validate()?;
+        self.frame_number += 1;
+        let frame_count = self.frames.len();
+        self.frames[frame_index % frame_count].uploads.clear();
+        self.recycle_texture_sets();
+        self.upload_user_textures();
+        if let Some(bindless) = &mut self.bindless {
+            bindless.prepare(frame_index % self.frames.len());
+        }
+        self.stats = UiPassStats {
+            meshes: paint_jobs.len(),
+            texture_uploads: std::mem::take(&mut self.texture_uploads),
+            gpu_time: self
+                .gpu_timer
+                .as_ref()
+                .and_then(|gpu_timer| gpu_timer.read(frame_index % self.frames.len())),
+            ..UiPassStats::default()
+        };
+
+        let frame_count = self.frames.len();
+        let device = &self.device;
+        let frame = &mut self.frames[frame_index % frame_count];
 
         let (logical_width, logical_height) = screen_descriptor.logical_size();
 
-        self.uniform_buffer
+        frame
+            .uniform_buffer
             .copy_from(bytemuck::cast_slice(&[UniformBuffer {
                 screen_size: [logical_width as f32, logical_height as f32],
             }]));
 
-        for (i, egui::ClippedMesh(_, mesh)) in paint_jobs.iter().enumerate() {
-            let data: &[u8] = bytemuck::cast_slice(&mesh.indices);
-            if i < index_size {
-                if self.index_buffers[i].size() != data.len() {
-                    self.index_buffers[i] = self.device.create_buffer_init(
-                        Some("index buffer"),
-                        data,
-                        vk::BufferUsageFlags::INDEX_BUFFER,
-                        maligog::MemoryLocation::CpuToGpu,
-                    );
-                } else {
-                    self.index_buffers[i].copy_from(data);
+        // Pack every mesh into one vertex and one index stream. Indices are rebased on the CPU so
+        // the vertex buffer is bound once and each draw only selects its range of indices.
+        self.vertex_data.clear();
+        self.index_data.clear();
+        frame.draws.clear();
+        frame.paint_jobs = paint_jobs.len();
+        let batch_clip_rects = self.descriptor.batch_clip_rects;
+        let mut vertex_count = 0u32;
+        for (paint_job, egui::ClippedMesh(clip_rect, mesh)) in paint_jobs.iter().enumerate() {
+            if batch_clip_rects {
+                if scissor_rect(clip_rect, screen_descriptor).is_none() {
+                    self.stats.skipped_clips += 1;
+                    continue;
+                }
+                // Every vertex carries its clip rect, so meshes using the same texture can be
+                // drawn together.
+                let clip_rect = [
+                    clip_rect.min.x,
+                    clip_rect.min.y,
+                    clip_rect.max.x,
+                    clip_rect.max.y,
+                ];
+                for vertex in &mesh.vertices {
+                    self.vertex_data
+                        .extend_from_slice(as_byte_slice(std::slice::from_ref(vertex)));
+                    self.vertex_data
+                        .extend_from_slice(bytemuck::cast_slice(&clip_rect));
                 }
             } else {
-                let buffer = self.device.create_buffer_init(
-                    Some("index buffer"),
-                    data,
-                    vk::BufferUsageFlags::INDEX_BUFFER,
-                    maligog::MemoryLocation::CpuToGpu,
-                );
-                self.index_buffers.push(buffer);
+                self.vertex_data
+                    .extend_from_slice(as_byte_slice(&mesh.vertices));
             }
 
-            let data: &[u8] = as_byte_slice(&mesh.vertices);
-            if i < vertex_size {
-                if self.vertex_buffers[i].size() != data.len() {
-                    self.vertex_buffers[i] = self.device.create_buffer_init(
-                        Some("vertex buffer"),
-                        data,
-                        vk::BufferUsageFlags::VERTEX_BUFFER,
-                        maligog::MemoryLocation::CpuToGpu,
-                    );
-                } else {
-                    self.vertex_buffers[i].copy_from(data);
+            let first_index = self.index_data.len() as u32;
+            self.index_data
+                .extend(mesh.indices.iter().map(|index| index + vertex_count));
+            vertex_count += mesh.vertices.len() as u32;
+            self.stats.vertices += mesh.vertices.len();
+            self.stats.indices += mesh.indices.len();
+
+            match frame.draws.last_mut() {
+                Some(draw)
+                    if batch_clip_rects
+                        && paint_jobs[draw.paint_job].1.texture_id == mesh.texture_id =>
+                {
+                    draw.index_count += mesh.indices.len() as u32;
+                }
+                _ => {
+                    frame.draws.push(Draw {
+                        first_index,
+                        index_count: mesh.indices.len() as u32,
+                        paint_job,
+                    })
                 }
-            } else {
-                let buffer = self.device.create_buffer_init(
-                    Some("vertex buffer"),
-                    data,
-                    vk::BufferUsageFlags::VERTEX_BUFFER,
-                    maligog::MemoryLocation::CpuToGpu,
-                );
-                self.vertex_buffers.push(buffer);
             }
         }
+
+        let index_data: &[u8] = bytemuck::cast_slice(&self.index_data);
+        let vertex_data: &[u8] = &self.vertex_data;
+        self.stats.uploaded_bytes =
+            std::mem::size_of::<UniformBuffer>() + index_data.len() + vertex_data.len();
+        if paint_jobs.is_empty() {
+            return Ok(self.stats);
+        }
+
+        let reallocated = [
+            reserve_buffer(
+                device,
+                &mut frame.index_buffer,
+                "index buffer",
+                index_data.len(),
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            ),
+            reserve_buffer(
+                device,
+                &mut frame.vertex_buffer,
+                "vertex buffer",
+                vertex_data.len(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+        ];
+        self.stats.buffer_allocations = reallocated.iter().filter(|&&created| created).count();
+        frame.index_buffer.as_ref().unwrap().copy_from(index_data);
+        frame.vertex_buffer.as_ref().unwrap().copy_from(vertex_data);
+        Ok(self.stats)
+    }
+}
+
+/// Transforms an egui clip rectangle in points to a scissor rectangle in physical pixels.
+///
+/// Returns `None` if the clip area is empty after clamping it to the target.
+fn scissor_rect(
+    clip_rect: &egui::Rect,
+    screen_descriptor: &ScreenDescriptor,
+) -> Option<vk::Rect2D> {
+    let scale_factor = screen_descriptor.scale_factor;
+    let viewport = screen_descriptor.viewport_rect();
+    let physical_width = viewport.extent.width;
+    let physical_height = viewport.extent.height;
+
+    // Transform clip rect to physical pixels.
+    let clip_min_x = scale_factor * clip_rect.min.x;
+    let clip_min_y = scale_factor * clip_rect.min.y;
+    let clip_max_x = scale_factor * clip_rect.max.x;
+    let clip_max_y = scale_factor * clip_rect.max.y;
+
+    // Make sure clip rect can fit within an `u32`.
+    let clip_min_x = clip_min_x.clamp(0.0, physical_width as f32);
+    let clip_min_y = clip_min_y.clamp(0.0, physical_height as f32);
+    let clip_max_x = clip_max_x.clamp(clip_min_x, physical_width as f32);
+    let clip_max_y = clip_max_y.clamp(clip_min_y, physical_height as f32);
+
+    let clip_min_x = clip_min_x.round() as u32;
+    let clip_min_y = clip_min_y.round() as u32;
+    let clip_max_x = clip_max_x.round() as u32;
+    let clip_max_y = clip_max_y.round() as u32;
+
+    let width = (clip_max_x - clip_min_x).max(1);
+    let height = (clip_max_y - clip_min_y).max(1);
+
+    // clip scissor rectangle to target size
+    let x = clip_min_x.min(physical_width);
+    let y = clip_min_y.min(physical_height);
+    let width = width.min(physical_width - x);
+    let height = height.min(physical_height - y);
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    Some(vk::Rect2D {
+        offset: vk::Offset2D {
+            x: viewport.offset.x + x as i32,
+            y: viewport.offset.y + y as i32,
+        },
+        extent: vk::Extent2D { width, height },
+    })
+}
+
+fn create_render_pass(device: &Device, descriptor: &UiPassDescriptor) -> maligog::RenderPass {
+    let color_reference = [vk::AttachmentReference::builder()
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .attachment(0)
+        .build()];
+
+    let mut attachments = Vec::new();
+    let resolve_reference;
+    let depth_reference;
+    let mut subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_reference);
+
+    if descriptor.sample_count == vk::SampleCountFlags::TYPE_1 {
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(descriptor.format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(descriptor.load_op)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(descriptor.initial_layout)
+                .final_layout(descriptor.final_layout)
+                .build(),
+        );
+    } else {
+        // Draw into a transient multisampled image and resolve it into the caller's image.
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(descriptor.format)
+                .samples(descriptor.sample_count)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .build(),
+        );
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(descriptor.format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(descriptor.initial_layout)
+                .final_layout(descriptor.final_layout)
+                .build(),
+        );
+        resolve_reference = [vk::AttachmentReference::builder()
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .attachment(1)
+            .build()];
+        subpass = subpass.resolve_attachments(&resolve_reference);
+    }
+
+    // The depth attachment always comes last.
+    if let Some(depth) = &descriptor.depth {
+        depth_reference = vk::AttachmentReference::builder()
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .attachment(attachments.len() as u32)
+            .build();
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(depth.format)
+                .samples(descriptor.sample_count)
+                .load_op(vk::AttachmentLoadOp::LOAD)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::LOAD)
+                .stencil_store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(depth.initial_layout)
+                .final_layout(depth.final_layout)
+                .build(),
+        );
+        subpass = subpass.depth_stencil_attachment(&depth_reference);
+    }
+
+    // Order the pass after the commands that last used the attachments and before the ones
+    // that use them next.
+    let attachment_scope = barrier::attachment_scope(descriptor.depth.is_some());
+    let target_barriers = descriptor
+        .target_barriers
+        .for_attachments(descriptor.depth.is_some());
+    let dependencies = [
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(target_barriers.src.stage_mask)
+            .src_access_mask(target_barriers.src.access_mask)
+            .dst_stage_mask(attachment_scope.stage_mask)
+            .dst_access_mask(attachment_scope.access_mask)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(attachment_scope.stage_mask)
+            .src_access_mask(attachment_scope.access_mask)
+            .dst_stage_mask(target_barriers.dst.stage_mask)
+            .dst_access_mask(target_barriers.dst.access_mask)
+            .build(),
+    ];
+
+    device.create_render_pass(
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&[subpass.build()])
+            .dependencies(&dependencies)
+            .build(),
+    )
+}
+
+fn create_graphics_pipeline(
+    device: &Device,
+    pipeline_layout: &maligog::PipelineLayout,
+    shader_modules: &ShaderModules,
+    render_pass: &maligog::RenderPass,
+    descriptor: &UiPassDescriptor,
+) -> maligog::GraphicsPipeline {
+    let vertex_entry = if descriptor.world_space {
+        "main_vs_world"
+    } else if descriptor.batch_clip_rects {
+        "main_vs_batched"
+    } else {
+        "main_vs"
+    };
+    let clip_in_shader = descriptor.world_space || descriptor.batch_clip_rects;
+    let bindless = descriptor.bindless_textures.is_some();
+    let fragment_entry = match (clip_in_shader, bindless, descriptor.output_color_space) {
+        (false, false, OutputColorSpace::Linear) => "main_fs",
+        (false, false, OutputColorSpace::Srgb) => "main_fs_unorm",
+        (false, true, OutputColorSpace::Linear) => "main_fs_bindless",
+        (false, true, OutputColorSpace::Srgb) => "main_fs_unorm_bindless",
+        // Bindless textures cannot be combined with shader clipping, see `validate`.
+        (true, _, OutputColorSpace::Linear) => "main_fs_clipped",
+        (true, _, OutputColorSpace::Srgb) => "main_fs_unorm_clipped",
+    };
+    // Bindless textures cannot be enabled after the modules were created, see
+    // `UiPass::set_descriptor`.
+    let fragment_module = match &shader_modules.bindless {
+        Some(bindless_module) if bindless => bindless_module,
+        _ => &shader_modules.main,
+    };
+    let mut vertex_attributes = vec![
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(0)
+            .build(),
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(4 * 2)
+            .build(),
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32_UINT)
+            .offset(4 * 4)
+            .build(),
+    ];
+    if descriptor.batch_clip_rects {
+        vertex_attributes.push(
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(4 * 5)
+                .build(),
+        );
+    }
+
+    let stages = vec![
+        maligog::ShaderStage::new(
+            &shader_modules.main,
+            maligog::ShaderStageFlags::VERTEX,
+            vertex_entry,
+        ),
+        maligog::ShaderStage::new(
+            fragment_module,
+            maligog::ShaderStageFlags::FRAGMENT,
+            fragment_entry,
+        ),
+    ];
+    // The create infos point into these arrays, which must outlive them.
+    let vertex_bindings = [vk::VertexInputBindingDescription::builder()
+        .stride(vertex_stride(descriptor.batch_clip_rects))
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .binding(0)
+        .build()];
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(true)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .build()];
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_bindings)
+        .vertex_attribute_descriptions(&vertex_attributes)
+        .build();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .build();
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .build();
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(descriptor.sample_count)
+        .build();
+    let depth_stencil_state = match &descriptor.depth {
+        Some(depth) => {
+            vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(true)
+                .depth_write_enable(depth.write)
+                .depth_compare_op(depth.compare_op)
+                .build()
+        }
+        None => vk::PipelineDepthStencilStateCreateInfo::default(),
+    };
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachments)
+        .build();
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states)
+        .build();
+
+    device.create_graphics_pipeline(
+        Some("egui pipeline"),
+        pipeline_layout,
+        stages,
+        render_pass,
+        &vertex_input_state,
+        &input_assembly_state,
+        &rasterization_state,
+        &multisample_state,
+        &depth_stencil_state,
+        &color_blend_state,
+        &viewport_state,
+        &dynamic_state,
+    )
+}
+
+/// Clears the part of the color attachment the UI is drawn into.
+fn clear_viewport(
+    recorder: &mut maligog::CommandRecorder,
+    color: vk::ClearColorValue,
+    viewport: vk::Rect2D,
+) {
+    recorder.clear_attachments(
+        &[vk::ClearAttachment::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .color_attachment(0)
+            .clear_value(vk::ClearValue { color })
+            .build()],
+        &[vk::ClearRect::builder()
+            .base_array_layer(0)
+            .layer_count(1)
+            .rect(viewport)
+            .build()],
+    )
+}
+
+fn texture_descriptor_update(
+    image_view: maligog::ImageView,
+    sampler: &maligog::Sampler,
+) -> std::collections::BTreeMap<u32, maligog::DescriptorUpdate> {
+    btreemap! {
+        0 => maligog::DescriptorUpdate::Image(vec![image_view]),
+        1 => maligog::DescriptorUpdate::Sampler(vec![sampler.clone()])
     }
 }
 
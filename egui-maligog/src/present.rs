@@ -0,0 +1,195 @@
+use maligog::{vk, Device};
+
+use crate::{Error, Result, ScreenDescriptor, UiPass, UiPassDescriptor, UiPassStats};
+
+/// Attempts at acquiring an image before a swapchain that stays out of date is reported as an
+/// error.
+const MAX_ACQUIRE_ATTEMPTS: usize = 3;
+
+/// Draws [`UiPass`] frames into the images of a swapchain and presents them.
+///
+/// The swapchain is recreated before the next frame whenever the window was resized or the
+/// surface reported it out of date or suboptimal, the cached framebuffers of the old images are
+/// dropped from the pass, and the screen descriptor takes the size of the new images.
+///
+/// The pass must end in `PRESENT_SRC_KHR`. Acquired images are in no known layout, so it must
+/// start from `UNDEFINED` and be given a clear color, or from `PRESENT_SRC_KHR` to draw over an
+/// image the application rendered into and left in that layout.
+pub struct Presenter {
+    device: Device,
+    swapchain: maligog::Swapchain,
+    screen_descriptor: ScreenDescriptor,
+    outdated: bool,
+}
+
+impl Presenter {
+    pub fn new(
+        device: &Device,
+        swapchain: maligog::Swapchain,
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f32,
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            swapchain,
+            screen_descriptor: ScreenDescriptor {
+                physical_width,
+                physical_height,
+                scale_factor,
+                viewport: None,
+            },
+            outdated: false,
+        }
+    }
+
+    /// The size and scale of the next frame.
+    pub fn screen_descriptor(&self) -> &ScreenDescriptor {
+        &self.screen_descriptor
+    }
+
+    /// Call this on `WindowEvent::Resized`. The swapchain is recreated before the next frame.
+    pub fn resize(&mut self, physical_width: u32, physical_height: u32) {
+        if physical_width != self.screen_descriptor.physical_width
+            || physical_height != self.screen_descriptor.physical_height
+        {
+            self.screen_descriptor.physical_width = physical_width;
+            self.screen_descriptor.physical_height = physical_height;
+            self.outdated = true;
+        }
+    }
+
+    /// Call this on `WindowEvent::ScaleFactorChanged`, together with [`Presenter::resize`] for
+    /// the new inner size.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.screen_descriptor.scale_factor = scale_factor;
+    }
+
+    /// Whether the window has no area, e.g. while it is minimized. Nothing is presented then.
+    pub fn is_minimized(&self) -> bool {
+        self.screen_descriptor.physical_width == 0 || self.screen_descriptor.physical_height == 0
+    }
+
+    /// Uploads `paint_jobs`, draws them into the next swapchain image and presents it, waiting
+    /// for the frame to finish.
+    ///
+    /// Returns `None` without drawing while the window is minimized. Fails with
+    /// [`Error::InvalidDescriptor`] if the layouts of the pass or `clear_color` do not fit a
+    /// swapchain image, see [`Presenter`].
+    pub fn render(
+        &mut self,
+        ui_pass: &mut UiPass,
+        frame_index: usize,
+        paint_jobs: &[egui::ClippedMesh],
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> Result<Option<UiPassStats>> {
+        check_layouts(ui_pass.descriptor())?;
+        if ui_pass.descriptor().initial_layout == vk::ImageLayout::UNDEFINED
+            && clear_color.is_none()
+        {
+            return Err(Error::InvalidDescriptor(
+                "a presented pass starting from UNDEFINED needs a clear color",
+            ));
+        }
+        let index = match self.acquire(ui_pass)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let stats = match self.draw(ui_pass, index, frame_index, paint_jobs, clear_color) {
+            Ok(stats) => stats,
+            Err(err) => {
+                // The image was never transitioned for presenting. Recreating the swapchain
+                // before the next frame releases it.
+                self.outdated = true;
+                return Err(err);
+            }
+        };
+        match self.swapchain.present(index, &[]) {
+            Ok(false) => {}
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.outdated = true,
+            Err(err) => return Err(Error::Swapchain(err)),
+        }
+        Ok(Some(stats))
+    }
+
+    /// Draws `paint_jobs` into the acquired image `index` and waits for it.
+    fn draw(
+        &mut self,
+        ui_pass: &mut UiPass,
+        index: u32,
+        frame_index: usize,
+        paint_jobs: &[egui::ClippedMesh],
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> Result<UiPassStats> {
+        ui_pass.update_buffers(frame_index, paint_jobs, &self.screen_descriptor)?;
+
+        let image = self.swapchain.get_image(index);
+        let mut cmd_buf = self.device.create_command_buffer(
+            Some("egui frame command buffer"),
+            self.device.graphics_queue_family_index(),
+        );
+        let mut result = Ok(UiPassStats::default());
+        cmd_buf.encode(|recorder| {
+            result = ui_pass.execute(
+                frame_index,
+                recorder,
+                &image,
+                paint_jobs,
+                &self.screen_descriptor,
+                clear_color,
+            );
+        });
+        let stats = result?;
+        self.device.graphics_queue().submit_blocking(&[cmd_buf]);
+        Ok(stats)
+    }
+
+    /// Acquires the next image, recreating the swapchain first if it is out of date.
+    fn acquire(&mut self, ui_pass: &mut UiPass) -> Result<Option<u32>> {
+        for _ in 0..MAX_ACQUIRE_ATTEMPTS {
+            if self.is_minimized() {
+                return Ok(None);
+            }
+            if self.outdated {
+                self.recreate(ui_pass);
+            }
+            match self.swapchain.acquire_next_image() {
+                Ok(index) => return Ok(Some(index)),
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.outdated = true,
+                Err(err) => return Err(Error::Swapchain(err)),
+            }
+        }
+        Err(Error::Swapchain(vk::Result::ERROR_OUT_OF_DATE_KHR))
+    }
+
+    fn recreate(&mut self, ui_pass: &mut UiPass) {
+        // The old images may still be read by the presentation engine. Their views are dropped
+        // before the swapchain destroys them.
+        self.device.wait_idle();
+        ui_pass.invalidate_targets();
+        self.swapchain.recreate();
+        self.outdated = false;
+        // The surface may have changed size without a resize event, e.g. when it was reported
+        // out of date. Frames are drawn at the size of the new images.
+        let image = self.swapchain.get_image(0);
+        self.screen_descriptor.physical_width = image.width();
+        self.screen_descriptor.physical_height = image.height();
+    }
+}
+
+/// Checks that a pass with `descriptor` can draw into swapchain images, see [`Presenter`].
+pub(crate) fn check_layouts(descriptor: &UiPassDescriptor) -> Result<()> {
+    if descriptor.final_layout != vk::ImageLayout::PRESENT_SRC_KHR {
+        return Err(Error::InvalidDescriptor(
+            "a presented pass must end in PRESENT_SRC_KHR",
+        ));
+    }
+    if descriptor.initial_layout != vk::ImageLayout::UNDEFINED
+        && descriptor.initial_layout != vk::ImageLayout::PRESENT_SRC_KHR
+    {
+        return Err(Error::InvalidDescriptor(
+            "a presented pass must start from UNDEFINED or PRESENT_SRC_KHR",
+        ));
+    }
+    Ok(())
+}
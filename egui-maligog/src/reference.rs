@@ -0,0 +1,458 @@
+//! A software renderer that mirrors what [`UiPass`](crate::UiPass) does on the GPU.
+//!
+//! It uses the same scissor computation as `UiPass::execute`, the colour conversions of
+//! `main_vs`/`main_fs` and the fixed-function blend state of the pipeline, so its output can be
+//! used as an oracle for the GPU path or as a fallback when no Vulkan device is available.
+
+use std::collections::HashMap;
+
+use maligog::vk;
+
+use crate::{scissor_rect, OutputColorSpace, SamplerDescriptor, ScreenDescriptor};
+
+/// A texture in the format the GPU samples it as, already decoded to linear values.
+struct SoftwareTexture {
+    width: usize,
+    height: usize,
+    /// Linear, premultiplied RGBA texels.
+    texels: Vec<[f32; 4]>,
+    sampler: SamplerDescriptor,
+}
+
+impl SoftwareTexture {
+    /// Samples like a Vulkan sampler created from `self.sampler`.
+    fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = u * self.width as f32;
+        let y = v * self.height as f32;
+        if self.sampler.filter == vk::Filter::NEAREST {
+            return self.texel(x.floor() as i64, y.floor() as i64);
+        }
+
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let lerp = |a: [f32; 4], b: [f32; 4], t: f32| {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+                a[3] + (b[3] - a[3]) * t,
+            ]
+        };
+        let top = lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), fx);
+        let bottom = lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), fx);
+        lerp(top, bottom, fy)
+    }
+
+    /// Fetches a texel, applying the address mode to out of range coordinates.
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let x = address(self.sampler.address_mode, x, self.width);
+        let y = address(self.sampler.address_mode, y, self.height);
+        self.texels[y * self.width + x]
+    }
+}
+
+fn address(mode: vk::SamplerAddressMode, coordinate: i64, size: usize) -> usize {
+    let size = size as i64;
+    let coordinate = match mode {
+        vk::SamplerAddressMode::REPEAT => coordinate.rem_euclid(size),
+        vk::SamplerAddressMode::MIRRORED_REPEAT => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size {
+                period
+            } else {
+                2 * size - 1 - period
+            }
+        }
+        _ => coordinate.clamp(0, size - 1),
+    };
+    coordinate as usize
+}
+
+/// Renders egui paint jobs into an RGBA8 pixel buffer on the CPU.
+pub struct ReferenceRenderer {
+    output_color_space: OutputColorSpace,
+    font_texture: Option<SoftwareTexture>,
+    user_textures: HashMap<u64, SoftwareTexture>,
+}
+
+impl ReferenceRenderer {
+    /// `output_color_space` selects the target format being emulated, `Linear` for
+    /// `R8G8B8A8_SRGB` and `Srgb` for `R8G8B8A8_UNORM`.
+    pub fn new(output_color_space: OutputColorSpace) -> Self {
+        Self {
+            output_color_space,
+            font_texture: None,
+            user_textures: HashMap::new(),
+        }
+    }
+
+    /// Equivalent of [`UiPass::update_texture`](crate::UiPass::update_texture).
+    pub fn update_texture(&mut self, egui_texture: &egui::Texture) {
+        // The GPU stores the coverage in an R8 image and swizzles it into every channel.
+        let texels = egui_texture
+            .pixels
+            .iter()
+            .map(|&coverage| [coverage as f32 / 255.0; 4])
+            .collect();
+        self.font_texture = Some(SoftwareTexture {
+            width: egui_texture.width,
+            height: egui_texture.height,
+            texels,
+            sampler: SamplerDescriptor::default(),
+        });
+    }
+
+    /// Equivalent of registering [`UserTexture::Rgba`](crate::UserTexture::Rgba) pixels.
+    pub fn set_user_texture(
+        &mut self,
+        id: egui::TextureId,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        sampler: SamplerDescriptor,
+    ) {
+        let id = match id {
+            egui::TextureId::User(id) => id,
+            egui::TextureId::Egui => panic!("the egui font texture is set by update_texture"),
+        };
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        // User textures are uploaded as `R8G8B8A8_SRGB`, which decodes to linear when sampled.
+        let texels = pixels
+            .chunks_exact(4)
+            .map(|texel| {
+                [
+                    linear_from_srgb(texel[0] as f32 / 255.0),
+                    linear_from_srgb(texel[1] as f32 / 255.0),
+                    linear_from_srgb(texel[2] as f32 / 255.0),
+                    texel[3] as f32 / 255.0,
+                ]
+            })
+            .collect();
+        self.user_textures.insert(
+            id,
+            SoftwareTexture {
+                width: width as usize,
+                height: height as usize,
+                texels,
+                sampler,
+            },
+        );
+    }
+
+    pub fn free_user_texture(&mut self, id: egui::TextureId) {
+        if let egui::TextureId::User(id) = id {
+            self.user_textures.remove(&id);
+        }
+    }
+
+    /// Renders `paint_jobs` and returns tightly packed RGBA8 rows of
+    /// `physical_width` x `physical_height` pixels.
+    ///
+    /// The whole image starts out as `clear_color`, including the part outside the viewport.
+    pub fn render(
+        &self,
+        paint_jobs: &[egui::paint::ClippedMesh],
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: [f32; 4],
+    ) -> Vec<u8> {
+        let width = screen_descriptor.physical_width as usize;
+        let height = screen_descriptor.physical_height as usize;
+        let clear = self.encode(clear_color);
+        let mut pixels = clear.repeat(width * height);
+
+        // `main_vs` maps positions from the truncated logical size to the whole viewport.
+        let viewport = screen_descriptor.viewport_rect();
+        let (logical_width, logical_height) = screen_descriptor.logical_size();
+        let scale_x = viewport.extent.width as f32 / logical_width as f32;
+        let scale_y = viewport.extent.height as f32 / logical_height as f32;
+
+        for egui::ClippedMesh(clip_rect, mesh) in paint_jobs {
+            let scissor = match scissor_rect(clip_rect, screen_descriptor) {
+                Some(scissor) => scissor,
+                None => continue,
+            };
+            let texture = match mesh.texture_id {
+                egui::TextureId::Egui => self.font_texture.as_ref(),
+                egui::TextureId::User(id) => self.user_textures.get(&id),
+            }
+            .expect("texture was not set before drawing");
+
+            let vertices: Vec<ShadedVertex> = mesh
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    let color = vertex.color;
+                    ShadedVertex {
+                        x: viewport.offset.x as f32 + vertex.pos.x * scale_x,
+                        y: viewport.offset.y as f32 + vertex.pos.y * scale_y,
+                        u: vertex.uv.x,
+                        v: vertex.uv.y,
+                        color: linear_from_color32([color.r(), color.g(), color.b(), color.a()]),
+                    }
+                })
+                .collect();
+
+            let min_x = scissor.offset.x as usize;
+            let min_y = scissor.offset.y as usize;
+            let max_x = min_x + scissor.extent.width as usize;
+            let max_y = min_y + scissor.extent.height as usize;
+
+            for triangle in mesh.indices.chunks_exact(3) {
+                let a = &vertices[triangle[0] as usize];
+                let b = &vertices[triangle[1] as usize];
+                let c = &vertices[triangle[2] as usize];
+                let area = edge(a, b, c.x, c.y);
+                if area == 0.0 {
+                    continue;
+                }
+                // The pipeline does not cull, so give every triangle a positive area.
+                let (b, c) = if area < 0.0 { (c, b) } else { (b, c) };
+                let area = area.abs();
+
+                let tri_min_x = (a.x.min(b.x).min(c.x).floor().max(0.0) as usize).max(min_x);
+                let tri_min_y = (a.y.min(b.y).min(c.y).floor().max(0.0) as usize).max(min_y);
+                let tri_max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as usize).min(max_x);
+                let tri_max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as usize).min(max_y);
+
+                for py in tri_min_y..tri_max_y {
+                    for px in tri_min_x..tri_max_x {
+                        let x = px as f32 + 0.5;
+                        let y = py as f32 + 0.5;
+                        let w0 = edge(b, c, x, y);
+                        let w1 = edge(c, a, x, y);
+                        let w2 = edge(a, b, x, y);
+                        if !covers(w0, b, c) || !covers(w1, c, a) || !covers(w2, a, b) {
+                            continue;
+                        }
+                        let (w0, w1, w2) = (w0 / area, w1 / area, w2 / area);
+                        let interpolate =
+                            |f: fn(&ShadedVertex) -> f32| w0 * f(a) + w1 * f(b) + w2 * f(c);
+
+                        let texel = texture.sample(interpolate(|v| v.u), interpolate(|v| v.v));
+                        let source = [
+                            interpolate(|v| v.color[0]) * texel[0],
+                            interpolate(|v| v.color[1]) * texel[1],
+                            interpolate(|v| v.color[2]) * texel[2],
+                            interpolate(|v| v.color[3]) * texel[3],
+                        ];
+
+                        let index = (py * width + px) * 4;
+                        let destination = self.decode(&pixels[index..index + 4]);
+                        let blended = blend(self.fragment_output(source), destination);
+                        pixels[index..index + 4].copy_from_slice(&self.encode(blended));
+                    }
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// What the fragment shader writes, in the space the blend unit works in.
+    fn fragment_output(&self, linear: [f32; 4]) -> [f32; 4] {
+        match self.output_color_space {
+            // The hardware decodes the destination of `*_SRGB` targets and blends linearly.
+            OutputColorSpace::Linear => linear,
+            OutputColorSpace::Srgb => srgb_from_linear_premultiplied(linear),
+        }
+    }
+
+    /// Converts a blend-space colour to the bytes stored in the target.
+    fn encode(&self, color: [f32; 4]) -> [u8; 4] {
+        let unorm = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self.output_color_space {
+            OutputColorSpace::Linear => {
+                [
+                    unorm(srgb_from_linear(color[0])),
+                    unorm(srgb_from_linear(color[1])),
+                    unorm(srgb_from_linear(color[2])),
+                    unorm(color[3]),
+                ]
+            }
+            OutputColorSpace::Srgb => {
+                [
+                    unorm(color[0]),
+                    unorm(color[1]),
+                    unorm(color[2]),
+                    unorm(color[3]),
+                ]
+            }
+        }
+    }
+
+    /// Converts stored target bytes to a blend-space colour.
+    fn decode(&self, bytes: &[u8]) -> [f32; 4] {
+        let float = |value: u8| value as f32 / 255.0;
+        match self.output_color_space {
+            OutputColorSpace::Linear => {
+                [
+                    linear_from_srgb(float(bytes[0])),
+                    linear_from_srgb(float(bytes[1])),
+                    linear_from_srgb(float(bytes[2])),
+                    float(bytes[3]),
+                ]
+            }
+            OutputColorSpace::Srgb => {
+                [
+                    float(bytes[0]),
+                    float(bytes[1]),
+                    float(bytes[2]),
+                    float(bytes[3]),
+                ]
+            }
+        }
+    }
+}
+
+/// Output of `main_vs` for a single vertex, in physical pixels.
+struct ShadedVertex {
+    x: f32,
+    y: f32,
+    u: f32,
+    v: f32,
+    color: [f32; 4],
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `(x, y)`.
+fn edge(a: &ShadedVertex, b: &ShadedVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Top-left fill rule, so pixels on edges shared by two triangles are only drawn once.
+fn covers(weight: f32, a: &ShadedVertex, b: &ShadedVertex) -> bool {
+    if weight != 0.0 {
+        return weight > 0.0;
+    }
+    let is_top = a.y == b.y && b.x > a.x;
+    let is_left = b.y < a.y;
+    is_top || is_left
+}
+
+/// The blend state of the egui pipeline: premultiplied colour, and
+/// `src * (1 - dst_alpha) + dst` for alpha.
+fn blend(source: [f32; 4], destination: [f32; 4]) -> [f32; 4] {
+    let one_minus_src_alpha = 1.0 - source[3];
+    [
+        source[0] + destination[0] * one_minus_src_alpha,
+        source[1] + destination[1] * one_minus_src_alpha,
+        source[2] + destination[2] * one_minus_src_alpha,
+        source[3] * (1.0 - destination[3]) + destination[3],
+    ]
+}
+
+// The colour conversions below are the CPU counterparts of the functions in the `shader` crate,
+// the tests at the end check that both agree.
+
+fn linear_from_srgb(srgb: f32) -> f32 {
+    if srgb < 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_from_linear(linear: f32) -> f32 {
+    if linear < 0.0031308 {
+        linear * 12.92
+    } else {
+        linear.powf(1.0 / 2.4) * 1.055 - 0.055
+    }
+}
+
+fn linear_from_color32(color: [u8; 4]) -> [f32; 4] {
+    let alpha = color[3] as f32 / 255.0;
+    let channel = |value: u8| {
+        let srgb = value as f32 / 255.0;
+        if alpha > 0.0 {
+            linear_from_srgb(srgb / alpha) * alpha
+        } else {
+            linear_from_srgb(srgb)
+        }
+    };
+    [
+        channel(color[0]),
+        channel(color[1]),
+        channel(color[2]),
+        alpha,
+    ]
+}
+
+fn srgb_from_linear_premultiplied(linear: [f32; 4]) -> [f32; 4] {
+    let alpha = linear[3];
+    let channel = |value: f32| {
+        if alpha > 0.0 {
+            srgb_from_linear(value / alpha) * alpha
+        } else {
+            srgb_from_linear(value)
+        }
+    };
+    [
+        channel(linear[0]),
+        channel(linear[1]),
+        channel(linear[2]),
+        alpha,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use shader::glam::{Vec3, Vec4};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Premultiplied `Color32` values, every channel at most the alpha.
+    fn premultiplied_colors() -> impl Iterator<Item = [u8; 4]> {
+        (0..=255u8).step_by(5).flat_map(|alpha| {
+            (0..=alpha)
+                .step_by(3)
+                .map(move |value| [value, alpha - value, value / 2, alpha])
+        })
+    }
+
+    #[test]
+    fn srgb_conversions_match_shader() {
+        for byte in 0..=255u8 {
+            let value = byte as f32 / 255.0;
+            assert_close(
+                super::linear_from_srgb(value),
+                shader::linear_from_srgb(Vec3::splat(value)).x,
+            );
+            assert_close(
+                super::srgb_from_linear(value),
+                shader::srgb_from_linear(Vec3::splat(value)).x,
+            );
+        }
+    }
+
+    #[test]
+    fn color32_decoding_matches_shader() {
+        for color in premultiplied_colors() {
+            let expected = shader::linear_from_color32(u32::from_le_bytes(color));
+            let actual = super::linear_from_color32(color);
+            for channel in 0..4 {
+                assert_close(actual[channel], expected[channel]);
+            }
+        }
+    }
+
+    #[test]
+    fn premultiplied_encoding_matches_shader() {
+        for color in premultiplied_colors() {
+            let linear = super::linear_from_color32(color);
+            let expected = shader::srgb_from_linear_premultiplied(Vec4::from(linear));
+            let actual = super::srgb_from_linear_premultiplied(linear);
+            for channel in 0..4 {
+                assert_close(actual[channel], expected[channel]);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use maligog::{vk, Device};
+
+/// How a texture is sampled.
+///
+/// Mipmapped sampling is not offered since maligog cannot create images with more than one mip
+/// level yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDescriptor {
+    /// Filter used for both magnification and minification.
+    pub filter: vk::Filter,
+    /// Addressing used for both texture coordinates.
+    pub address_mode: vk::SamplerAddressMode,
+}
+
+impl SamplerDescriptor {
+    /// Blocky sampling with wrap-around, for pixel art.
+    pub const NEAREST_REPEAT: Self = Self {
+        filter: vk::Filter::NEAREST,
+        address_mode: vk::SamplerAddressMode::REPEAT,
+    };
+}
+
+impl Default for SamplerDescriptor {
+    /// Linear filtering clamped to the edge, which is also used for the font atlas.
+    fn default() -> Self {
+        Self {
+            filter: vk::Filter::LINEAR,
+            address_mode: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+/// Creates each distinct sampler once.
+pub(crate) struct SamplerCache {
+    device: Device,
+    samplers: HashMap<SamplerDescriptor, maligog::Sampler>,
+}
+
+impl SamplerCache {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            device: device.clone(),
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, descriptor: SamplerDescriptor) -> maligog::Sampler {
+        let device = &self.device;
+        self.samplers
+            .entry(descriptor)
+            .or_insert_with(|| {
+                device.create_sampler(
+                    Some("egui sampler"),
+                    descriptor.filter,
+                    descriptor.filter,
+                    descriptor.address_mode,
+                    descriptor.address_mode,
+                )
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use maligog::{vk, Device};
+
+use crate::{Error, Result};
+
+/// Counters of the last frame, returned by [`UiPass::update_buffers`] and [`UiPass::execute`].
+///
+/// [`UiPass::update_buffers`]: crate::UiPass::update_buffers
+/// [`UiPass::execute`]: crate::UiPass::execute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UiPassStats {
+    /// Paint jobs of the frame.
+    pub meshes: usize,
+    /// `draw_indexed` calls recorded.
+    pub draw_calls: usize,
+    /// Paint jobs skipped because their clip rect has no area on the target.
+    pub skipped_clips: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    /// Bytes written to the uniform, vertex and index buffers.
+    pub uploaded_bytes: usize,
+    /// Vertex and index buffers created because the old ones were too small.
+    pub buffer_allocations: usize,
+    /// Font atlas and user texture uploads since the previous frame.
+    pub texture_uploads: usize,
+    /// GPU time of the render pass of the last frame that used the same frame index, if
+    /// [`UiPassDescriptor::gpu_timing`](crate::UiPassDescriptor::gpu_timing) is set and the
+    /// results are available.
+    pub gpu_time: Option<Duration>,
+}
+
+/// Timestamp queries around the render pass, one pair per frame in flight.
+pub(crate) struct GpuTimer {
+    device: Device,
+    query_pool: maligog::QueryPool,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    /// The bits of a timestamp the queue writes, the others are undefined.
+    timestamp_mask: u64,
+    /// Whether the queries of each frame have been written since they were created.
+    written: Vec<bool>,
+}
+
+impl GpuTimer {
+    /// Fails if the graphics queue does not support timestamps.
+    pub fn new(device: &Device, frames_in_flight: usize) -> Result<Self> {
+        // `timestampComputeAndGraphics` only promises valid bits on every graphics queue, so the
+        // queue the frames are submitted to is checked directly.
+        let valid_bits = device.timestamp_valid_bits(device.graphics_queue_family_index());
+        if valid_bits == 0 {
+            return Err(Error::InvalidDescriptor(
+                "gpu_timing needs a graphics queue with timestamp support",
+            ));
+        }
+        Ok(Self {
+            device: device.clone(),
+            query_pool: device
+                .create_query_pool(vk::QueryType::TIMESTAMP, 2 * frames_in_flight as u32),
+            timestamp_period: device.timestamp_period(),
+            timestamp_mask: timestamp_mask(valid_bits),
+            written: vec![false; frames_in_flight],
+        })
+    }
+
+    /// The GPU must be idle when the number of frames changes.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        if frames_in_flight != self.written.len() {
+            self.query_pool = self
+                .device
+                .create_query_pool(vk::QueryType::TIMESTAMP, 2 * frames_in_flight as u32);
+            self.written = vec![false; frames_in_flight];
+        }
+    }
+
+    /// Must be recorded outside of a render pass.
+    pub fn begin(&mut self, recorder: &mut maligog::CommandRecorder, frame: usize) {
+        let first_query = 2 * frame as u32;
+        recorder.reset_query_pool(&self.query_pool, first_query, 2);
+        recorder.write_timestamp(
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            &self.query_pool,
+            first_query,
+        );
+    }
+
+    /// Must be recorded outside of a render pass.
+    pub fn end(&mut self, recorder: &mut maligog::CommandRecorder, frame: usize) {
+        recorder.write_timestamp(
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            &self.query_pool,
+            2 * frame as u32 + 1,
+        );
+        self.written[frame] = true;
+    }
+
+    /// Reads the time measured the last time `frame` was recorded, without waiting.
+    pub fn read(&self, frame: usize) -> Option<Duration> {
+        if !self.written[frame] {
+            return None;
+        }
+        let timestamps = self.query_pool.get_results_u64(2 * frame as u32, 2)?;
+        let ticks = elapsed_ticks(timestamps[0], timestamps[1], self.timestamp_mask);
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.timestamp_period as f64) as u64,
+        ))
+    }
+}
+
+/// The bits a queue with `valid_bits` timestamp bits writes, `valid_bits` must not be zero.
+fn timestamp_mask(valid_bits: u32) -> u64 {
+    u64::MAX >> (64 - valid_bits.min(64))
+}
+
+/// Ticks from `start` to `end`, ignoring the undefined bits outside of `mask`.
+fn elapsed_ticks(start: u64, end: u64, mask: u64) -> u64 {
+    // Timestamps with fewer valid bits wrap around sooner, which the mask accounts for.
+    (end & mask).wrapping_sub(start & mask) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{elapsed_ticks, timestamp_mask};
+
+    #[test]
+    fn mask_keeps_valid_bits() {
+        assert_eq!(timestamp_mask(36), (1 << 36) - 1);
+        assert_eq!(timestamp_mask(64), u64::MAX);
+    }
+
+    #[test]
+    fn undefined_bits_are_ignored() {
+        let mask = timestamp_mask(36);
+        let start = 0xdead << 36 | 0x10;
+        let end = 0xbeef << 36 | 0x30;
+        assert_eq!(elapsed_ticks(start, end, mask), 0x20);
+    }
+
+    #[test]
+    fn wrap_around_is_counted_within_valid_bits() {
+        let mask = timestamp_mask(36);
+        assert_eq!(elapsed_ticks(mask - 4, 6, mask), 11);
+        assert_eq!(elapsed_ticks(u64::MAX - 4, 6, u64::MAX), 11);
+    }
+}
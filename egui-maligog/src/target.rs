@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use maligog::{vk, Device};
+
+use crate::UiPassDescriptor;
+
+/// Number of [`TargetCache::begin_frame`] calls a target may go unused before its framebuffer is
+/// dropped. Must cover the swapchain length, otherwise images would be evicted between uses.
+const MAX_UNUSED_FRAMES: u64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct TargetKey {
+    image: vk::Image,
+    depth_image: Option<vk::Image>,
+    width: u32,
+    height: u32,
+}
+
+struct CachedTarget {
+    /// Clones of the images the key was made from. They keep the handles alive while they are
+    /// cached, so a new image cannot be created with the same handle and hit this entry.
+    _image: maligog::Image,
+    _depth_image: Option<maligog::Image>,
+    _image_view: maligog::ImageView,
+    /// Transient multisampled image resolved into the target, if the pass uses MSAA.
+    _msaa_image_view: Option<maligog::ImageView>,
+    _depth_image_view: Option<maligog::ImageView>,
+    framebuffer: maligog::Framebuffer,
+    last_used: u64,
+}
+
+/// Image views and framebuffers of the images `UiPass` rendered into, keyed by image and extent.
+///
+/// Targets that have not been used for a while are evicted, which releases the images dropped by
+/// the caller. Swapchain images are destroyed with their swapchain regardless, so their targets
+/// must be dropped with [`TargetCache::clear`] when it is recreated.
+pub(crate) struct TargetCache {
+    targets: HashMap<TargetKey, CachedTarget>,
+    frame_number: u64,
+}
+
+impl TargetCache {
+    pub fn new() -> Self {
+        Self {
+            targets: HashMap::new(),
+            frame_number: 0,
+        }
+    }
+
+    /// Advances the frame counter and evicts targets unused for longer than `frames_in_flight`
+    /// and [`MAX_UNUSED_FRAMES`].
+    pub fn begin_frame(&mut self, frames_in_flight: usize) {
+        self.frame_number += 1;
+        let max_age = MAX_UNUSED_FRAMES.max(frames_in_flight as u64);
+        let frame_number = self.frame_number;
+        self.targets
+            .retain(|_, target| frame_number - target.last_used <= max_age);
+    }
+
+    pub fn framebuffer(
+        &mut self,
+        device: &Device,
+        render_pass: &maligog::RenderPass,
+        descriptor: &UiPassDescriptor,
+        image: &maligog::Image,
+        depth_image: Option<&maligog::Image>,
+        width: u32,
+        height: u32,
+    ) -> maligog::Framebuffer {
+        let key = TargetKey {
+            image: image.handle(),
+            depth_image: depth_image.map(maligog::Image::handle),
+            width,
+            height,
+        };
+        let frame_number = self.frame_number;
+        let target = self.targets.entry(key).or_insert_with(|| {
+            let image_view = image.create_view();
+            let msaa_image_view = if descriptor.sample_count == vk::SampleCountFlags::TYPE_1 {
+                None
+            } else {
+                let msaa_image = device.create_multisample_image(
+                    Some("egui msaa target"),
+                    descriptor.format,
+                    width,
+                    height,
+                    descriptor.sample_count,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                    maligog::MemoryLocation::GpuOnly,
+                );
+                Some(msaa_image.create_view())
+            };
+            let depth_image_view = depth_image.map(maligog::Image::create_view);
+            // The multisampled image is attachment 0, the resolve target attachment 1, and the
+            // depth image comes last.
+            let mut attachments = match &msaa_image_view {
+                Some(msaa_image_view) => vec![msaa_image_view, &image_view],
+                None => vec![&image_view],
+            };
+            attachments.extend(&depth_image_view);
+            let framebuffer =
+                device.create_framebuffer(render_pass.clone(), width, height, attachments);
+            CachedTarget {
+                _image: image.clone(),
+                _depth_image: depth_image.cloned(),
+                _image_view: image_view,
+                _msaa_image_view: msaa_image_view,
+                _depth_image_view: depth_image_view,
+                framebuffer,
+                last_used: frame_number,
+            }
+        });
+        target.last_used = frame_number;
+        target.framebuffer.clone()
+    }
+
+    /// Drops every cached view and framebuffer. The GPU must be done with them.
+    pub fn clear(&mut self) {
+        self.targets.clear();
+    }
+}
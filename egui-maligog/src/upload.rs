@@ -0,0 +1,108 @@
+use maligog::{vk, Device};
+
+use crate::barrier::{self, AccessScope};
+
+/// A copy of pixels into a sampled image, recorded into the command buffer of the next frame.
+///
+/// Once recorded, the upload is kept with the frame so the staging buffer lives until the GPU is
+/// done with it.
+pub(crate) struct PendingUpload {
+    staging_buffer: maligog::Buffer,
+    image: maligog::Image,
+    /// `UNDEFINED` for new images, whose contents are all replaced.
+    old_layout: vk::ImageLayout,
+    region: vk::BufferImageCopy,
+}
+
+impl PendingUpload {
+    /// Uploads `pixels` into the rows starting at `first_row` of `image`.
+    pub fn new(
+        device: &Device,
+        image: &maligog::Image,
+        old_layout: vk::ImageLayout,
+        pixels: &[u8],
+        first_row: u32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let staging_buffer = device.create_buffer_init(
+            Some("egui texture staging buffer"),
+            pixels,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            maligog::MemoryLocation::CpuToGpu,
+        );
+        Self {
+            staging_buffer,
+            image: image.clone(),
+            old_layout,
+            region: vk::BufferImageCopy::builder()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .layer_count(1)
+                        .build(),
+                )
+                .image_offset(vk::Offset3D {
+                    x: 0,
+                    y: first_row as i32,
+                    z: 0,
+                })
+                .image_extent(vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                })
+                .build(),
+        }
+    }
+
+    /// Records the copy, leaving the image ready to be sampled by fragment shaders.
+    ///
+    /// Must be recorded outside of a render pass.
+    pub fn record(&self, recorder: &mut maligog::CommandRecorder) {
+        let transfer_write = AccessScope {
+            stage_mask: vk::PipelineStageFlags::TRANSFER,
+            access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        };
+        let shader_read = AccessScope {
+            stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            access_mask: vk::AccessFlags::SHADER_READ,
+        };
+        // Earlier frames may still sample the image, only their reads have to finish.
+        let before = if self.old_layout == vk::ImageLayout::UNDEFINED {
+            AccessScope {
+                stage_mask: vk::PipelineStageFlags::TOP_OF_PIPE,
+                access_mask: vk::AccessFlags::empty(),
+            }
+        } else {
+            AccessScope {
+                stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                access_mask: vk::AccessFlags::empty(),
+            }
+        };
+        barrier::image_barrier(
+            recorder,
+            &self.image,
+            vk::ImageAspectFlags::COLOR,
+            self.old_layout,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            before,
+            transfer_write,
+        );
+        recorder.copy_buffer_to_image(
+            &self.staging_buffer,
+            &self.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[self.region],
+        );
+        barrier::image_barrier(
+            recorder,
+            &self.image,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::READ_ONLY_OPTIMAL_KHR,
+            transfer_write,
+            shader_read,
+        );
+    }
+}
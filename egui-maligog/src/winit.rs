@@ -0,0 +1,131 @@
+use std::time::Instant;
+
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use maligog::{vk, Device};
+use winit_crate::event::{Event, WindowEvent};
+use winit_crate::window::{Window, WindowId};
+
+use crate::present::{self, Presenter};
+use crate::{Result, UiPass, UiPassDescriptor, UiPassStats};
+
+/// Draws an egui UI into a winit window: owns the egui input state, the swapchain and the
+/// [`UiPass`] rendering into it.
+///
+/// Forward every event of the event loop to [`EguiWinitRenderer::handle_event`] and call
+/// [`EguiWinitRenderer::render`] on `RedrawRequested`.
+pub struct EguiWinitRenderer {
+    window_id: WindowId,
+    presenter: Presenter,
+    platform: Platform,
+    ui_pass: UiPass,
+    start_time: Instant,
+    frame_index: usize,
+    paint_jobs: Vec<egui::ClippedMesh>,
+}
+
+impl EguiWinitRenderer {
+    /// Creates a swapchain for `window`. `descriptor.format` must be the format of its images,
+    /// see [`Presenter`] for the layouts.
+    pub fn new(
+        instance: &maligog::Instance,
+        device: &Device,
+        window: &Window,
+        present_mode: maligog::PresentModeKHR,
+        descriptor: &UiPassDescriptor,
+    ) -> Result<Self> {
+        present::check_layouts(descriptor)?;
+        let size = window.inner_size();
+        let scale_factor = window.scale_factor();
+        let surface = instance.create_surface(window);
+        let swapchain = device.create_swapchain(surface, present_mode);
+        let ui_pass = UiPass::new(device, descriptor)?;
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor,
+            font_definitions: egui::FontDefinitions::default(),
+            style: egui::Style::default(),
+        });
+        Ok(Self {
+            window_id: window.id(),
+            presenter: Presenter::new(
+                device,
+                swapchain,
+                size.width,
+                size.height,
+                scale_factor as f32,
+            ),
+            platform,
+            ui_pass,
+            start_time: Instant::now(),
+            frame_index: 0,
+            paint_jobs: Vec::new(),
+        })
+    }
+
+    /// Feeds input to egui and resizes the swapchain with the window. Events of other windows are
+    /// ignored.
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        if let Event::WindowEvent { window_id, event } = event {
+            if *window_id != self.window_id {
+                return;
+            }
+            match event {
+                WindowEvent::Resized(size) => {
+                    self.presenter.resize(size.width, size.height);
+                }
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.presenter.set_scale_factor(*scale_factor as f32);
+                    self.presenter
+                        .resize(new_inner_size.width, new_inner_size.height);
+                }
+                _ => {}
+            }
+        }
+        self.platform.handle_event(event);
+    }
+
+    pub fn context(&self) -> egui::CtxRef {
+        self.platform.context()
+    }
+
+    /// The pass drawing the UI, to register user textures with.
+    pub fn ui_pass(&mut self) -> &mut UiPass {
+        &mut self.ui_pass
+    }
+
+    /// Runs `ui` for a new egui frame, draws it into the next swapchain image cleared to
+    /// `clear_color` and presents it.
+    ///
+    /// Nothing is drawn while the window is minimized. The frame is waited on before returning,
+    /// see [`Presenter::render`].
+    pub fn render(
+        &mut self,
+        clear_color: [f32; 4],
+        ui: impl FnOnce(&egui::CtxRef),
+    ) -> Result<(egui::Output, UiPassStats)> {
+        self.platform
+            .update_time(self.start_time.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+        ui(&self.platform.context());
+        let (output, shapes) = self.platform.end_frame();
+        self.paint_jobs = self.platform.context().tessellate(shapes);
+
+        let frame_index = self.frame_index;
+        self.frame_index = (self.frame_index + 1) % self.ui_pass.descriptor().frames_in_flight;
+        self.ui_pass
+            .update_texture(&self.platform.context().texture())?;
+        let stats = self.presenter.render(
+            &mut self.ui_pass,
+            frame_index,
+            &self.paint_jobs,
+            Some(vk::ClearColorValue {
+                float32: clear_color,
+            }),
+        )?;
+        Ok((output, stats.unwrap_or_default()))
+    }
+}
@@ -0,0 +1,1250 @@
+//! Renders deterministic egui frames headlessly and compares them against the PNG images in
+//! `tests/golden`.
+//!
+//! Run `EGUI_MALIGOG_BLESS=1 cargo test -p egui-maligog --test golden` to (re)write the golden
+//! images, see `tests/golden/README.md`. On a mismatch the rendered frame and a diff image are
+//! written to `target/golden-diff`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use egui_maligog::{
+    AccessScope, DepthDescriptor, Error, OutputColorSpace, ReferenceRenderer, SamplerDescriptor,
+    ScreenDescriptor, TargetBarriers, UiPass, UiPassDescriptor, UiPassStats, UserTexture,
+};
+use maligog::vk;
+
+/// Largest per-channel difference that still counts as a matching pixel.
+const CHANNEL_TOLERANCE: u8 = 3;
+/// Number of pixels allowed to differ by more than [`CHANNEL_TOLERANCE`].
+const MAX_MISMATCHED_PIXELS: usize = 16;
+
+struct Harness {
+    _instance: maligog::Instance,
+    device: maligog::Device,
+}
+
+impl Harness {
+    fn new() -> Self {
+        env_logger::builder()
+            .filter_level(log::LevelFilter::Warn)
+            .try_init()
+            .ok();
+
+        let entry = maligog::Entry::new().unwrap();
+        let instance = entry.create_instance(&[], &[]);
+        let pdevice = instance
+            .enumerate_physical_device()
+            .first()
+            .expect("no Vulkan device, install lavapipe to run the golden tests without a GPU")
+            .to_owned();
+        let device = pdevice.create_device();
+        Self {
+            _instance: instance,
+            device,
+        }
+    }
+
+    /// Runs `ui` with fixed input and returns the rendered RGBA8 pixels.
+    fn render(
+        &self,
+        width: u32,
+        height: u32,
+        scale_factor: f32,
+        ui: impl Fn(&egui::CtxRef),
+    ) -> Vec<u8> {
+        let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+        self.render_with(&descriptor, width, height, scale_factor, ui)
+            .0
+    }
+
+    /// Like [`Harness::render`] with a custom pass, also returning the pass to inspect.
+    fn render_with(
+        &self,
+        descriptor: &UiPassDescriptor,
+        width: u32,
+        height: u32,
+        scale_factor: f32,
+        ui: impl Fn(&egui::CtxRef),
+    ) -> (Vec<u8>, UiPass) {
+        let (paint_jobs, texture) = tessellate(width, height, scale_factor, ui);
+        let mut ui_pass = UiPass::new(&self.device, descriptor).unwrap();
+        ui_pass.update_texture(&texture).unwrap();
+        let pixels = ui_pass
+            .render_to_image(
+                &paint_jobs,
+                &ScreenDescriptor {
+                    physical_width: width,
+                    physical_height: height,
+                    scale_factor,
+                    viewport: None,
+                },
+                [0.0, 0.0, 0.0, 1.0],
+            )
+            .unwrap();
+        (pixels, ui_pass)
+    }
+
+    /// Records the `window` UI with [`UiPass::execute`] into a new image without submitting it,
+    /// to check whether the pass refuses to record.
+    fn record_window(
+        &self,
+        descriptor: &UiPassDescriptor,
+        screen_descriptor: &ScreenDescriptor,
+        clear_color: Option<vk::ClearColorValue>,
+    ) -> egui_maligog::Result<UiPassStats> {
+        let width = screen_descriptor.physical_width;
+        let height = screen_descriptor.physical_height;
+        let (paint_jobs, texture) = tessellate(width, height, 1.0, window);
+        let mut ui_pass = UiPass::new(&self.device, descriptor).unwrap();
+        ui_pass.update_texture(&texture).unwrap();
+        ui_pass
+            .update_buffers(0, &paint_jobs, screen_descriptor)
+            .unwrap();
+        self.record_unsubmitted(descriptor.format, width, height, |recorder, image| {
+            ui_pass.execute(
+                0,
+                recorder,
+                image,
+                &paint_jobs,
+                screen_descriptor,
+                clear_color,
+            )
+        })
+    }
+
+    /// Lets `record` draw into a new RGBA8 image and leave it in `TRANSFER_SRC_OPTIMAL`, then
+    /// returns its pixels.
+    fn read_back(
+        &self,
+        width: u32,
+        height: u32,
+        record: impl FnOnce(
+            &mut maligog::CommandRecorder,
+            &maligog::Image,
+        ) -> egui_maligog::Result<UiPassStats>,
+    ) -> Vec<u8> {
+        let device = &self.device;
+        let image = device.create_image(
+            Some("readback target"),
+            vk::Format::R8G8B8A8_UNORM,
+            width,
+            height,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            maligog::MemoryLocation::GpuOnly,
+        );
+        let readback_buffer = device.create_buffer(
+            Some("readback buffer"),
+            (width * height * 4) as usize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            maligog::MemoryLocation::GpuToCpu,
+        );
+
+        let mut cmd_buf = device.create_command_buffer(
+            Some("readback command buffer"),
+            device.graphics_queue_family_index(),
+        );
+        let mut record = Some(record);
+        let mut result = Ok(UiPassStats::default());
+        cmd_buf.encode(|recorder| {
+            result = (record.take().unwrap())(recorder, &image);
+            if result.is_ok() {
+                copy_to_buffer(recorder, &image, &readback_buffer, width, height);
+            }
+        });
+        result.unwrap();
+        device.graphics_queue().submit_blocking(&[cmd_buf]);
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        readback_buffer.copy_to(&mut pixels);
+        pixels
+    }
+
+    /// Lets `record` record into a new image without submitting it.
+    fn record_unsubmitted(
+        &self,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        record: impl FnOnce(
+            &mut maligog::CommandRecorder,
+            &maligog::Image,
+        ) -> egui_maligog::Result<UiPassStats>,
+    ) -> egui_maligog::Result<UiPassStats> {
+        let device = &self.device;
+        let image = device.create_image(
+            Some("unsubmitted target"),
+            format,
+            width,
+            height,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            maligog::MemoryLocation::GpuOnly,
+        );
+
+        let mut cmd_buf = device.create_command_buffer(
+            Some("unsubmitted command buffer"),
+            device.graphics_queue_family_index(),
+        );
+        let mut record = Some(record);
+        let mut result = Ok(UiPassStats::default());
+        cmd_buf.encode(|recorder| {
+            result = (record.take().unwrap())(recorder, &image);
+        });
+        result
+    }
+}
+
+/// Runs `ui` with fixed input and returns the paint jobs and font texture of the frame.
+fn tessellate(
+    width: u32,
+    height: u32,
+    scale_factor: f32,
+    ui: impl Fn(&egui::CtxRef),
+) -> (Vec<egui::ClippedMesh>, Arc<egui::Texture>) {
+    let mut ctx = egui::CtxRef::default();
+    ctx.set_fonts(egui::FontDefinitions::default());
+    let mut style = egui::Style::default();
+    style.animation_time = 0.0;
+    ctx.set_style(style);
+
+    let raw_input = || {
+        egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                Default::default(),
+                egui::vec2(width as f32, height as f32) / scale_factor,
+            )),
+            pixels_per_point: Some(scale_factor),
+            time: Some(0.0),
+            ..Default::default()
+        }
+    };
+
+    // Windows size themselves during their first frame, so only the second one is drawn.
+    ctx.begin_frame(raw_input());
+    ui(&ctx);
+    ctx.end_frame();
+    ctx.begin_frame(raw_input());
+    ui(&ctx);
+    let (_, shapes) = ctx.end_frame();
+    (ctx.tessellate(shapes), ctx.texture())
+}
+
+fn screen_descriptor(width: u32, height: u32, scale_factor: f32) -> ScreenDescriptor {
+    ScreenDescriptor {
+        physical_width: width,
+        physical_height: height,
+        scale_factor,
+        viewport: None,
+    }
+}
+
+fn msaa_descriptor() -> UiPassDescriptor {
+    UiPassDescriptor {
+        sample_count: vk::SampleCountFlags::TYPE_4,
+        ..UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap()
+    }
+}
+
+const BLACK: vk::ClearColorValue = vk::ClearColorValue {
+    float32: [0.0, 0.0, 0.0, 1.0],
+};
+
+/// Records a copy of a `TRANSFER_SRC_OPTIMAL` RGBA8 image into `buffer`.
+fn copy_to_buffer(
+    recorder: &mut maligog::CommandRecorder,
+    image: &maligog::Image,
+    buffer: &maligog::Buffer,
+    width: u32,
+    height: u32,
+) {
+    recorder.copy_image_to_buffer(
+        image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        buffer,
+        &[vk::BufferImageCopy::builder()
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .build()],
+    );
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn diff_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/golden-diff")
+}
+
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let (info, mut reader) = decoder.read_info().unwrap();
+    assert_eq!(info.color_type, png::ColorType::RGBA);
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    (info.width, info.height, pixels)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+}
+
+/// Compares `pixels` against `tests/golden/<name>.png`.
+fn assert_golden(name: &str, width: u32, height: u32, pixels: &[u8]) {
+    let golden_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("EGUI_MALIGOG_BLESS").is_some() {
+        write_png(&golden_path, width, height, pixels);
+        return;
+    }
+    assert!(
+        golden_path.exists(),
+        "{} is missing, see tests/golden/README.md to create it",
+        golden_path.display()
+    );
+
+    let (golden_width, golden_height, golden) = read_png(&golden_path);
+    assert_eq!(
+        (golden_width, golden_height),
+        (width, height),
+        "{}: size differs from the golden image",
+        name
+    );
+    assert_similar(name, width, height, pixels, &golden);
+}
+
+/// Compares two RGBA8 images, writing the actual image and a diff image on failure.
+fn assert_similar(name: &str, width: u32, height: u32, pixels: &[u8], expected: &[u8]) {
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(pixels.len());
+    for (actual, expected) in pixels.chunks_exact(4).zip(expected.chunks_exact(4)) {
+        let max_difference = actual
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| (*a as i16 - *b as i16).abs() as u8)
+            .max()
+            .unwrap();
+        if max_difference > CHANNEL_TOLERANCE {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // Dimmed copy of the expected pixel, so the mismatches stand out.
+            diff.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+        }
+    }
+
+    if mismatched > MAX_MISMATCHED_PIXELS {
+        let actual_path = diff_dir().join(format!("{}.actual.png", name));
+        let diff_path = diff_dir().join(format!("{}.diff.png", name));
+        write_png(&actual_path, width, height, pixels);
+        write_png(&diff_path, width, height, &diff);
+        panic!(
+            "{}: {} pixels differ from the expected image, see {} and {}",
+            name,
+            mismatched,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn label_and_button(ctx: &egui::CtxRef) {
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.heading("egui-maligog");
+        ui.label("The quick brown fox jumps over the lazy dog.");
+        let _ = ui.button("Button");
+        ui.separator();
+        ui.checkbox(&mut true, "Checked");
+    });
+}
+
+fn window(ctx: &egui::CtxRef) {
+    egui::Window::new("Window")
+        .default_pos(egui::pos2(16.0, 16.0))
+        .show(ctx, |ui| {
+            ui.label("Window contents");
+            ui.add(egui::Slider::new(&mut 0.5f32, 0.0..=1.0).text("value"));
+        });
+}
+
+/// Shows a 64×64 point image of `texture_id`.
+fn user_image(texture_id: egui::TextureId) -> impl Fn(&egui::CtxRef) {
+    move |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.image(texture_id, egui::vec2(64.0, 64.0));
+        });
+    }
+}
+
+/// Shows a 64×64 point image of each of `texture_ids` in a row.
+fn user_images(texture_ids: Vec<egui::TextureId>) -> impl Fn(&egui::CtxRef) {
+    move |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for &texture_id in &texture_ids {
+                    ui.image(texture_id, egui::vec2(64.0, 64.0));
+                }
+            });
+        });
+    }
+}
+
+/// An 8×8 RGBA8 checkerboard of 4×4 blocks in two colours.
+fn checkerboard(a: [u8; 4], b: [u8; 4]) -> Vec<u8> {
+    (0..64)
+        .flat_map(|i| {
+            if (i % 8 / 4 + i / 8 / 4) % 2 == 0 {
+                a
+            } else {
+                b
+            }
+        })
+        .collect()
+}
+
+fn user_texture_index(id: egui::TextureId) -> u64 {
+    match id {
+        egui::TextureId::User(id) => id,
+        egui::TextureId::Egui => panic!("not a user texture"),
+    }
+}
+
+#[test]
+fn golden_label_and_button() {
+    let harness = Harness::new();
+    let pixels = harness.render(320, 240, 1.0, label_and_button);
+    assert_golden("label_and_button", 320, 240, &pixels);
+}
+
+#[test]
+fn golden_label_and_button_hidpi() {
+    let harness = Harness::new();
+    let pixels = harness.render(640, 480, 2.0, label_and_button);
+    assert_golden("label_and_button_hidpi", 640, 480, &pixels);
+}
+
+#[test]
+fn golden_window() {
+    let harness = Harness::new();
+    let pixels = harness.render(320, 240, 1.0, window);
+    assert_golden("window", 320, 240, &pixels);
+}
+
+#[test]
+fn golden_window_msaa() {
+    let harness = Harness::new();
+    let (pixels, _) = harness.render_with(&msaa_descriptor(), 320, 240, 1.0, window);
+    assert_golden("window_msaa", 320, 240, &pixels);
+}
+
+#[test]
+fn multisampled_pass_needs_clear_color() {
+    let harness = Harness::new();
+    let result = harness.record_window(&msaa_descriptor(), &screen_descriptor(320, 240, 1.0), None);
+    assert!(
+        matches!(result, Err(Error::InvalidDescriptor(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn multisampled_pass_rejects_viewport() {
+    let harness = Harness::new();
+    let result = harness.record_window(
+        &msaa_descriptor(),
+        &ScreenDescriptor {
+            viewport: Some(vk::Rect2D {
+                offset: vk::Offset2D { x: 40, y: 30 },
+                extent: vk::Extent2D {
+                    width: 200,
+                    height: 160,
+                },
+            }),
+            ..screen_descriptor(320, 240, 1.0)
+        },
+        Some(BLACK),
+    );
+    assert!(
+        matches!(result, Err(Error::InvalidDescriptor(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn execute_rejects_other_paint_jobs() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let screen_descriptor = screen_descriptor(320, 240, 1.0);
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, window);
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    ui_pass.update_texture(&texture).unwrap();
+    ui_pass
+        .update_buffers(0, &paint_jobs, &screen_descriptor)
+        .unwrap();
+    let result = harness.record_unsubmitted(descriptor.format, 320, 240, |recorder, image| {
+        ui_pass.execute(
+            0,
+            recorder,
+            image,
+            &paint_jobs[..1],
+            &screen_descriptor,
+            Some(BLACK),
+        )
+    });
+    assert_eq!(
+        result,
+        Err(Error::PaintJobCountMismatch {
+            uploaded: paint_jobs.len(),
+            recorded: 1,
+        })
+    );
+}
+
+#[test]
+fn headless_rejects_other_formats() {
+    let result = UiPassDescriptor::headless(vk::Format::B8G8R8A8_UNORM);
+    assert!(
+        matches!(result, Err(Error::InvalidDescriptor(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn drawing_before_font_texture_fails() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let (paint_jobs, _) = tessellate(320, 240, 1.0, window);
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let result = ui_pass.render_to_image(
+        &paint_jobs,
+        &screen_descriptor(320, 240, 1.0),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_eq!(result, Err(Error::MissingFontTexture));
+}
+
+#[test]
+fn drawing_unknown_user_texture_fails() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_image(egui::TextureId::User(7)));
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    ui_pass.update_texture(&texture).unwrap();
+    let result = ui_pass.render_to_image(
+        &paint_jobs,
+        &screen_descriptor(320, 240, 1.0),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_eq!(result, Err(Error::UnknownUserTexture(7)));
+}
+
+#[test]
+fn drawing_freed_user_texture_fails() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let id = ui_pass
+        .register_user_texture(
+            UserTexture::Rgba {
+                width: 8,
+                height: 8,
+                pixels: checkerboard([255, 255, 255, 255], [0, 0, 255, 255]),
+            },
+            SamplerDescriptor::NEAREST_REPEAT,
+        )
+        .unwrap();
+    ui_pass.free_user_texture(id);
+
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_image(id));
+    ui_pass.update_texture(&texture).unwrap();
+    let result = ui_pass.render_to_image(
+        &paint_jobs,
+        &screen_descriptor(320, 240, 1.0),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_eq!(result, Err(Error::FreedUserTexture(user_texture_index(id))));
+}
+
+#[test]
+fn user_textures_match_reference() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let screen_descriptor = screen_descriptor(320, 240, 1.0);
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let mut reference = ReferenceRenderer::new(OutputColorSpace::Srgb);
+
+    let pixels = checkerboard([255, 255, 255, 255], [0, 0, 255, 255]);
+    let id = ui_pass
+        .register_user_texture(
+            UserTexture::Rgba {
+                width: 8,
+                height: 8,
+                pixels: pixels.clone(),
+            },
+            SamplerDescriptor::NEAREST_REPEAT,
+        )
+        .unwrap();
+    reference.set_user_texture(id, 8, 8, &pixels, SamplerDescriptor::NEAREST_REPEAT);
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_image(id));
+    ui_pass.update_texture(&texture).unwrap();
+    reference.update_texture(&texture);
+    let rendered = ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    let expected = reference.render(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    assert_similar("user_texture", 320, 240, &rendered, &expected);
+
+    // Updating replaces both the pixels and the sampler.
+    let pixels = checkerboard([255, 0, 0, 128], [0, 255, 0, 255]);
+    ui_pass
+        .update_user_texture(
+            id,
+            UserTexture::Rgba {
+                width: 8,
+                height: 8,
+                pixels: pixels.clone(),
+            },
+            SamplerDescriptor::default(),
+        )
+        .unwrap();
+    reference.set_user_texture(id, 8, 8, &pixels, SamplerDescriptor::default());
+    let rendered = ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    let expected = reference.render(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    assert_similar("updated_user_texture", 320, 240, &rendered, &expected);
+
+    ui_pass.free_user_texture(id);
+    let result = ui_pass.render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(result, Err(Error::FreedUserTexture(user_texture_index(id))));
+}
+
+#[test]
+fn stats_count_known_frame() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let screen_descriptor = screen_descriptor(320, 240, 1.0);
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let id = ui_pass
+        .register_user_texture(
+            UserTexture::Rgba {
+                width: 8,
+                height: 8,
+                pixels: checkerboard([255, 255, 255, 255], [0, 0, 255, 255]),
+            },
+            SamplerDescriptor::NEAREST_REPEAT,
+        )
+        .unwrap();
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_image(id));
+    ui_pass.update_texture(&texture).unwrap();
+
+    let vertices: usize = paint_jobs.iter().map(|job| job.1.vertices.len()).sum();
+    let indices: usize = paint_jobs.iter().map(|job| job.1.indices.len()).sum();
+    let frame = UiPassStats {
+        meshes: paint_jobs.len(),
+        draw_calls: paint_jobs.len(),
+        skipped_clips: 0,
+        vertices,
+        indices,
+        // The screen size uniform, the vertices and the u32 indices.
+        uploaded_bytes: 8 + vertices * std::mem::size_of::<egui::paint::Vertex>() + indices * 4,
+        buffer_allocations: 2,
+        // The font atlas and the user texture.
+        texture_uploads: 2,
+        gpu_time: None,
+    };
+    ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    assert_eq!(ui_pass.stats(), frame);
+
+    // Drawing the same frame again reuses the buffers and textures.
+    ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    assert_eq!(
+        ui_pass.stats(),
+        UiPassStats {
+            buffer_allocations: 0,
+            texture_uploads: 0,
+            ..frame
+        }
+    );
+}
+
+#[test]
+fn placeholder_replaces_freed_user_texture() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor {
+        substitute_missing_textures: true,
+        ..UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap()
+    };
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let id = ui_pass
+        .register_user_texture(
+            UserTexture::Rgba {
+                width: 8,
+                height: 8,
+                pixels: checkerboard([255, 255, 255, 255], [0, 0, 255, 255]),
+            },
+            SamplerDescriptor::NEAREST_REPEAT,
+        )
+        .unwrap();
+    ui_pass.free_user_texture(id);
+
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_image(id));
+    ui_pass.update_texture(&texture).unwrap();
+    let screen_descriptor = screen_descriptor(320, 240, 1.0);
+    let pixels = ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+
+    // The placeholder is a magenta and black checkerboard.
+    let mut reference = ReferenceRenderer::new(OutputColorSpace::Srgb);
+    reference.update_texture(&texture);
+    reference.set_user_texture(
+        id,
+        8,
+        8,
+        &checkerboard([255, 0, 255, 255], [0, 0, 0, 255]),
+        SamplerDescriptor::NEAREST_REPEAT,
+    );
+    let expected = reference.render(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    assert_similar("placeholder_image", 320, 240, &pixels, &expected);
+}
+
+#[test]
+fn viewport_keeps_pixels_outside() {
+    let harness = Harness::new();
+    let device = &harness.device;
+    let headless = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let viewport = vk::Rect2D {
+        offset: vk::Offset2D { x: 40, y: 30 },
+        extent: vk::Extent2D {
+            width: 200,
+            height: 160,
+        },
+    };
+    let full_screen = screen_descriptor(320, 240, 1.0);
+    let screen_descriptor = ScreenDescriptor {
+        viewport: Some(viewport),
+        ..screen_descriptor(320, 240, 1.0)
+    };
+    let (paint_jobs, texture) = tessellate(200, 160, 1.0, window);
+
+    // A first pass fills the target with blue, standing in for the scene. The UI pass loads it
+    // and must only clear and draw inside the viewport.
+    let attachment_access = AccessScope {
+        stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    };
+    let mut scene_pass = UiPass::new(
+        device,
+        &UiPassDescriptor {
+            final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            target_barriers: TargetBarriers {
+                src: AccessScope::NONE,
+                dst: attachment_access,
+            },
+            ..headless.clone()
+        },
+    )
+    .unwrap();
+    let mut ui_pass = UiPass::new(
+        device,
+        &UiPassDescriptor {
+            initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            target_barriers: TargetBarriers {
+                src: attachment_access,
+                dst: AccessScope::TRANSFER_READ,
+            },
+            ..headless.clone()
+        },
+    )
+    .unwrap();
+    scene_pass.update_buffers(0, &[], &full_screen).unwrap();
+    ui_pass.update_texture(&texture).unwrap();
+    ui_pass
+        .update_buffers(0, &paint_jobs, &screen_descriptor)
+        .unwrap();
+
+    let image = device.create_image(
+        Some("viewport target"),
+        headless.format,
+        320,
+        240,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        maligog::MemoryLocation::GpuOnly,
+    );
+    let readback_buffer = device.create_buffer(
+        Some("readback buffer"),
+        320 * 240 * 4,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        maligog::MemoryLocation::GpuToCpu,
+    );
+    let mut cmd_buf = device.create_command_buffer(
+        Some("viewport command buffer"),
+        device.graphics_queue_family_index(),
+    );
+    let mut result = Ok(());
+    cmd_buf.encode(|recorder| {
+        let blue = vk::ClearColorValue {
+            float32: [0.0, 0.0, 1.0, 1.0],
+        };
+        result = scene_pass
+            .execute(0, recorder, &image, &[], &full_screen, Some(blue))
+            .and_then(|_| {
+                ui_pass.execute(
+                    0,
+                    recorder,
+                    &image,
+                    &paint_jobs,
+                    &screen_descriptor,
+                    Some(BLACK),
+                )
+            })
+            .map(|_| copy_to_buffer(recorder, &image, &readback_buffer, 320, 240));
+    });
+    result.unwrap();
+    device.graphics_queue().submit_blocking(&[cmd_buf]);
+    let mut pixels = vec![0; 320 * 240 * 4];
+    readback_buffer.copy_to(&mut pixels);
+
+    let mut reference = ReferenceRenderer::new(OutputColorSpace::Srgb);
+    reference.update_texture(&texture);
+    let mut expected = reference.render(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    for y in 0..240 {
+        for x in 0..320 {
+            let inside = x >= viewport.offset.x
+                && x < viewport.offset.x + viewport.extent.width as i32
+                && y >= viewport.offset.y
+                && y < viewport.offset.y + viewport.extent.height as i32;
+            if !inside {
+                let index = ((y * 320 + x) * 4) as usize;
+                expected[index..index + 4].copy_from_slice(&[0, 0, 255, 255]);
+            }
+        }
+    }
+    assert_similar("viewport_window", 320, 240, &pixels, &expected);
+}
+
+#[test]
+fn depth_rejects_ui_behind_scene() {
+    let harness = Harness::new();
+    let device = &harness.device;
+    let headless = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let screen_descriptor = screen_descriptor(320, 240, 1.0);
+    let (scene_jobs, scene_texture) = tessellate(320, 240, 1.0, label_and_button);
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, window);
+    let depth_image = device.create_image(
+        Some("scene depth"),
+        vk::Format::D32_SFLOAT,
+        320,
+        240,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        maligog::MemoryLocation::GpuOnly,
+    );
+
+    // A first pass stands in for the scene. Its panel covers the screen and writes a depth of
+    // 0.5, which the window is then drawn behind or in front of.
+    let attachment_access = AccessScope {
+        stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    };
+    let scene_descriptor = UiPassDescriptor {
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        target_barriers: TargetBarriers {
+            src: AccessScope::NONE,
+            dst: attachment_access,
+        },
+        depth: Some(DepthDescriptor {
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            compare_op: vk::CompareOp::ALWAYS,
+            write: true,
+            ..DepthDescriptor::default()
+        }),
+        ..headless.clone()
+    };
+    let ui_descriptor = UiPassDescriptor {
+        initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        load_op: vk::AttachmentLoadOp::LOAD,
+        target_barriers: TargetBarriers {
+            src: attachment_access,
+            dst: AccessScope::TRANSFER_READ,
+        },
+        depth: Some(DepthDescriptor::default()),
+        ..headless.clone()
+    };
+    let render = |depth: f32| {
+        let mut scene_pass = UiPass::new(device, &scene_descriptor).unwrap();
+        let mut ui_pass = UiPass::new(device, &ui_descriptor).unwrap();
+        scene_pass.update_texture(&scene_texture).unwrap();
+        scene_pass
+            .update_buffers(0, &scene_jobs, &screen_descriptor)
+            .unwrap();
+        ui_pass.update_texture(&texture).unwrap();
+        ui_pass
+            .update_buffers(0, &paint_jobs, &screen_descriptor)
+            .unwrap();
+        harness.read_back(320, 240, |recorder, image| {
+            scene_pass
+                .execute_with_depth(
+                    0,
+                    recorder,
+                    image,
+                    &depth_image,
+                    &scene_jobs,
+                    &vec![0.5; scene_jobs.len()],
+                    &screen_descriptor,
+                    Some(BLACK),
+                )
+                .and_then(|_| {
+                    ui_pass.execute_with_depth(
+                        0,
+                        recorder,
+                        image,
+                        &depth_image,
+                        &paint_jobs,
+                        &vec![depth; paint_jobs.len()],
+                        &screen_descriptor,
+                        None,
+                    )
+                })
+        })
+    };
+
+    let scene = harness.render(320, 240, 1.0, label_and_button);
+    assert_similar("depth_behind", 320, 240, &render(0.75), &scene);
+    assert!(
+        render(0.25) != scene,
+        "the window was not drawn in front of the scene"
+    );
+}
+
+#[test]
+fn world_transform_places_ui() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor {
+        world_space: true,
+        ..UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap()
+    };
+    let (paint_jobs, texture) = tessellate(640, 480, 2.0, window);
+    let screen_descriptor = screen_descriptor(640, 480, 2.0);
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    ui_pass.update_texture(&texture).unwrap();
+    ui_pass
+        .update_buffers(0, &paint_jobs, &screen_descriptor)
+        .unwrap();
+
+    // Scales the 320×240 points of the UI onto the whole clip space, which is what drawing it on
+    // the screen at a scale factor of two does.
+    let transform = [
+        [1.0 / 160.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0 / 120.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-1.0, -1.0, 0.0, 1.0],
+    ];
+    let pixels = harness.read_back(640, 480, |recorder, image| {
+        ui_pass.execute_world(
+            0,
+            recorder,
+            image,
+            None,
+            &paint_jobs,
+            transform,
+            &screen_descriptor,
+            Some(BLACK),
+        )
+    });
+
+    let mut reference = ReferenceRenderer::new(OutputColorSpace::Srgb);
+    reference.update_texture(&texture);
+    let expected = reference.render(&paint_jobs, &screen_descriptor, [0.0, 0.0, 0.0, 1.0]);
+    assert_similar("world_window", 640, 480, &pixels, &expected);
+}
+
+#[test]
+fn reference_renderer_matches_gpu() {
+    let harness = Harness::new();
+    let pixels = harness.render(320, 240, 1.0, window);
+
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, window);
+    let mut reference = ReferenceRenderer::new(OutputColorSpace::Srgb);
+    reference.update_texture(&texture);
+    let expected = reference.render(
+        &paint_jobs,
+        &ScreenDescriptor {
+            physical_width: 320,
+            physical_height: 240,
+            scale_factor: 1.0,
+            viewport: None,
+        },
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_similar("reference_window", 320, 240, &pixels, &expected);
+}
+
+#[test]
+fn batched_clip_rects_match_scissors() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let (pixels, ui_pass) = harness.render_with(&descriptor, 320, 240, 1.0, window);
+    let (batched, batched_pass) = harness.render_with(
+        &UiPassDescriptor {
+            batch_clip_rects: true,
+            ..descriptor
+        },
+        320,
+        240,
+        1.0,
+        window,
+    );
+    assert_similar("batched_window", 320, 240, &batched, &pixels);
+    let draw_calls = ui_pass.stats().draw_calls;
+    let batched_draw_calls = batched_pass.stats().draw_calls;
+    assert!(
+        batched_draw_calls < draw_calls,
+        "{} batched draw calls, {} without batching",
+        batched_draw_calls,
+        draw_calls
+    );
+}
+
+#[test]
+fn bindless_textures_match_descriptor_sets() {
+    let harness = Harness::new();
+    let pixels = harness.render(320, 240, 1.0, window);
+    let (bindless, _) = harness.render_with(
+        &UiPassDescriptor {
+            bindless_textures: Some(16),
+            ..UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap()
+        },
+        320,
+        240,
+        1.0,
+        window,
+    );
+    assert_similar("bindless_window", 320, 240, &bindless, &pixels);
+}
+
+#[test]
+fn bindless_user_textures_match_descriptor_sets() {
+    let harness = Harness::new();
+    let render = |descriptor: &UiPassDescriptor| {
+        let mut ui_pass = UiPass::new(&harness.device, descriptor).unwrap();
+        let texture_ids = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+            .iter()
+            .map(|&color| {
+                ui_pass
+                    .register_user_texture(
+                        UserTexture::Rgba {
+                            width: 8,
+                            height: 8,
+                            pixels: checkerboard(color, [255, 255, 255, 255]),
+                        },
+                        SamplerDescriptor::NEAREST_REPEAT,
+                    )
+                    .unwrap()
+            })
+            .collect();
+        let (paint_jobs, texture) = tessellate(320, 240, 1.0, user_images(texture_ids));
+        ui_pass.update_texture(&texture).unwrap();
+        let pixels = ui_pass
+            .render_to_image(
+                &paint_jobs,
+                &screen_descriptor(320, 240, 1.0),
+                [0.0, 0.0, 0.0, 1.0],
+            )
+            .unwrap();
+        (pixels, ui_pass.stats())
+    };
+
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let (pixels, stats) = render(&descriptor);
+    let (bindless, bindless_stats) = render(&UiPassDescriptor {
+        bindless_textures: Some(16),
+        ..descriptor
+    });
+    assert_similar("bindless_user_images", 320, 240, &bindless, &pixels);
+    // The array saves binding a set per paint job, draws are not merged across textures.
+    assert_eq!(bindless_stats.draw_calls, stats.draw_calls);
+}
+
+#[test]
+fn bindless_slots_are_reused_after_frame() {
+    let harness = Harness::new();
+    let descriptor = UiPassDescriptor {
+        bindless_textures: Some(4),
+        ..UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap()
+    };
+    let texture = || {
+        UserTexture::Rgba {
+            width: 8,
+            height: 8,
+            pixels: checkerboard([255, 255, 255, 255], [0, 0, 255, 255]),
+        }
+    };
+    let mut ui_pass = UiPass::new(&harness.device, &descriptor).unwrap();
+    let (paint_jobs, font_texture) = tessellate(320, 240, 1.0, label_and_button);
+    ui_pass.update_texture(&font_texture).unwrap();
+
+    // The font and placeholder leave two slots for user textures.
+    let first = ui_pass
+        .register_user_texture(texture(), SamplerDescriptor::NEAREST_REPEAT)
+        .unwrap();
+    ui_pass
+        .register_user_texture(texture(), SamplerDescriptor::NEAREST_REPEAT)
+        .unwrap();
+    let too_many = Err(Error::TooManyTextures { capacity: 4 });
+    assert_eq!(
+        ui_pass.register_user_texture(texture(), SamplerDescriptor::NEAREST_REPEAT),
+        too_many
+    );
+
+    // The freed slot may still be sampled by the frame in flight.
+    ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor(320, 240, 1.0), [0.0; 4])
+        .unwrap();
+    ui_pass.free_user_texture(first);
+    assert_eq!(
+        ui_pass.register_user_texture(texture(), SamplerDescriptor::NEAREST_REPEAT),
+        too_many
+    );
+    ui_pass
+        .render_to_image(&paint_jobs, &screen_descriptor(320, 240, 1.0), [0.0; 4])
+        .unwrap();
+    ui_pass
+        .register_user_texture(texture(), SamplerDescriptor::NEAREST_REPEAT)
+        .unwrap();
+}
+
+#[test]
+fn external_render_pass_matches_execute() {
+    let harness = Harness::new();
+    let device = &harness.device;
+    let descriptor = UiPassDescriptor::headless(vk::Format::R8G8B8A8_UNORM).unwrap();
+    let pixels = harness.render(320, 240, 1.0, window);
+
+    // A single subpass render pass standing in for the caller's scene pass.
+    let render_pass = device.create_render_pass(
+        &vk::RenderPassCreateInfo::builder()
+            .attachments(&[vk::AttachmentDescription::builder()
+                .format(descriptor.format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .build()])
+            .subpasses(&[vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&[vk::AttachmentReference::builder()
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .attachment(0)
+                    .build()])
+                .build()])
+            // The target is read back by a copy afterwards.
+            .dependencies(&[vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .build()])
+            .build(),
+    );
+    let image = device.create_image(
+        Some("scene target"),
+        descriptor.format,
+        320,
+        240,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        maligog::MemoryLocation::GpuOnly,
+    );
+    let image_view = image.create_view();
+    let framebuffer = device.create_framebuffer(render_pass.clone(), 320, 240, vec![&image_view]);
+    let readback_buffer = device.create_buffer(
+        Some("readback buffer"),
+        320 * 240 * 4,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        maligog::MemoryLocation::GpuToCpu,
+    );
+
+    let (paint_jobs, texture) = tessellate(320, 240, 1.0, window);
+    let screen_descriptor = ScreenDescriptor {
+        physical_width: 320,
+        physical_height: 240,
+        scale_factor: 1.0,
+        viewport: None,
+    };
+    let mut ui_pass = UiPass::new(device, &descriptor).unwrap();
+    ui_pass.set_external_render_pass(&render_pass);
+    ui_pass.update_texture(&texture).unwrap();
+    ui_pass
+        .update_buffers(0, &paint_jobs, &screen_descriptor)
+        .unwrap();
+
+    let mut cmd_buf = device.create_command_buffer(
+        Some("scene command buffer"),
+        device.graphics_queue_family_index(),
+    );
+    cmd_buf.encode(|recorder| {
+        ui_pass.record_uploads(0, recorder);
+        recorder.begin_render_pass(&render_pass, &framebuffer, |recorder| {
+            recorder.clear_attachments(
+                &[vk::ClearAttachment::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .color_attachment(0)
+                    .clear_value(vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 0.0, 1.0],
+                        },
+                    })
+                    .build()],
+                &[vk::ClearRect::builder()
+                    .layer_count(1)
+                    .rect(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent: vk::Extent2D {
+                            width: 320,
+                            height: 240,
+                        },
+                    })
+                    .build()],
+            );
+            ui_pass
+                .record_draws(0, recorder, &paint_jobs, None, &screen_descriptor)
+                .unwrap();
+        });
+        copy_to_buffer(recorder, &image, &readback_buffer, 320, 240);
+    });
+    device.graphics_queue().submit_blocking(&[cmd_buf]);
+
+    let mut external = vec![0; 320 * 240 * 4];
+    readback_buffer.copy_to(&mut external);
+    assert_similar("external_window", 320, 240, &external, &pixels);
+}
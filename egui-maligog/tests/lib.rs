@@ -1,10 +1,13 @@
+extern crate winit_crate as winit;
+
 use std::array::IntoIter;
 use std::collections::BTreeMap;
 use std::iter::FromIterator;
 use std::time::Duration;
 
-use egui_maligog::ScreenDescriptor;
+use egui_maligog::Presenter;
 use egui_maligog::UiPass;
+use egui_maligog::UiPassDescriptor;
 use maligog::vk;
 use maligog::BufferView;
 
@@ -19,11 +22,8 @@ struct Engine {
     instance: maligog::Instance,
     device: maligog::Device,
     image: maligog::Image,
-    swapchain: maligog::Swapchain,
+    presenter: Presenter,
     ui_pass: egui_maligog::UiPass,
-    width: u32,
-    height: u32,
-    scale_factor: f32,
     egui_instance: egui_winit_platform::Platform,
     start_time: std::time::Instant,
     paint_jobs: Vec<egui::ClippedMesh>,
@@ -67,7 +67,15 @@ impl Engine {
             }],
         );
 
-        let ui_pass = UiPass::new(&device);
+        let ui_pass = UiPass::new(
+            &device,
+            &UiPassDescriptor {
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                load_op: vk::AttachmentLoadOp::DONT_CARE,
+                ..UiPassDescriptor::default()
+            },
+        )
+        .unwrap();
         let egui_instance =
             egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
                 physical_width: width,
@@ -77,15 +85,13 @@ impl Engine {
                 style: egui::Style::default(),
             });
         let start_time = std::time::Instant::now();
+        let presenter = Presenter::new(&device, swapchain, width, height, scale_factor as f32);
         Self {
             instance,
             device,
             image,
-            swapchain,
+            presenter,
             ui_pass,
-            width,
-            height,
-            scale_factor: scale_factor as f32,
             egui_instance,
             start_time,
             paint_jobs: Vec::new(),
@@ -94,6 +100,22 @@ impl Engine {
 
     pub fn update(&mut self, event: &winit::event::Event<()>) {
         self.egui_instance.handle_event(event);
+        if let winit::event::Event::WindowEvent { event, .. } = event {
+            match event {
+                winit::event::WindowEvent::Resized(size) => {
+                    self.presenter.resize(size.width, size.height);
+                }
+                winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.presenter.set_scale_factor(*scale_factor as f32);
+                    self.presenter
+                        .resize(new_inner_size.width, new_inner_size.height);
+                }
+                _ => {}
+            }
+        }
 
         self.egui_instance
             .update_time(self.start_time.elapsed().as_secs_f64());
@@ -115,55 +137,27 @@ impl Engine {
         });
         let (_, paint_commands) = self.egui_instance.end_frame();
         self.paint_jobs = self.egui_instance.context().tessellate(paint_commands);
-        self.ui_pass.update_buffers(
-            &self.paint_jobs,
-            &ScreenDescriptor {
-                physical_width: self.width,
-                physical_height: self.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
         self.ui_pass
-            .update_texture(&self.egui_instance.context().texture());
+            .update_texture(&self.egui_instance.context().texture())
+            .unwrap();
     }
 
     pub fn render(&mut self) {
-        let mut cmd_buf = self.device.create_command_buffer(
-            Some("frame command buffer"),
-            self.device.graphics_queue_family_index(),
-        );
-        let index = self.swapchain.acquire_next_image().unwrap();
-        let image = self.swapchain.get_image(index);
-        cmd_buf.encode(|recorder| {
-            // self.swapchain.get_image(index).set_layout(
-            //     maligog::ImageLayout::UNDEFINED,
-            //     maligog::ImageLayout::PRESENT_SRC_KHR,
-            // );
-
-            self.ui_pass.execute(
-                recorder,
-                &image,
+        self.presenter
+            .render(
+                &mut self.ui_pass,
+                0,
                 &self.paint_jobs,
-                &ScreenDescriptor {
-                    physical_width: self.width,
-                    physical_height: self.height,
-                    scale_factor: self.scale_factor as f32,
-                },
                 Some(vk::ClearColorValue {
                     float32: [1.0, 1.0, 1.0, 1.0],
                 }),
-            );
-        });
-        self.device.graphics_queue().submit_blocking(&[cmd_buf]);
-        image.set_layout(
-            vk::ImageLayout::ATTACHMENT_OPTIMAL_KHR,
-            vk::ImageLayout::PRESENT_SRC_KHR,
-        );
-        self.swapchain.present(index, &[]);
+            )
+            .unwrap();
     }
 }
 
 #[test]
+#[ignore = "opens a window and runs until it is closed"]
 fn test_general() {
     env_logger::builder()
         .filter_level(log::LevelFilter::Trace)
@@ -0,0 +1,170 @@
+//! Checks of the software reference renderer, these run without a Vulkan device.
+
+use egui::{Color32, Pos2, Rect};
+use egui_maligog::{OutputColorSpace, ReferenceRenderer, SamplerDescriptor, ScreenDescriptor};
+use maligog::vk;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 8;
+
+fn renderer(output_color_space: OutputColorSpace) -> ReferenceRenderer {
+    let mut renderer = ReferenceRenderer::new(output_color_space);
+    // A single fully covered texel, like the white pixel egui keeps at the atlas origin.
+    renderer.update_texture(&egui::Texture {
+        version: 0,
+        width: 1,
+        height: 1,
+        pixels: vec![255],
+    });
+    renderer
+}
+
+fn screen_descriptor() -> ScreenDescriptor {
+    ScreenDescriptor {
+        physical_width: WIDTH,
+        physical_height: HEIGHT,
+        scale_factor: 1.0,
+        viewport: None,
+    }
+}
+
+fn quad(rect: Rect, color: Color32) -> egui::paint::Mesh {
+    let mut mesh = egui::paint::Mesh::default();
+    mesh.add_colored_rect(rect, color);
+    mesh
+}
+
+fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+    let index = ((y * WIDTH + x) * 4) as usize;
+    [
+        pixels[index],
+        pixels[index + 1],
+        pixels[index + 2],
+        pixels[index + 3],
+    ]
+}
+
+#[test]
+fn opaque_quad_covers_every_pixel_once() {
+    let screen = Rect::from_min_max(Pos2::ZERO, Pos2::new(WIDTH as f32, HEIGHT as f32));
+    let paint_jobs = vec![egui::ClippedMesh(
+        screen,
+        quad(screen, Color32::from_rgb(255, 0, 0)),
+    )];
+    let pixels = renderer(OutputColorSpace::Srgb).render(
+        &paint_jobs,
+        &screen_descriptor(),
+        [0.0, 0.0, 0.0, 0.0],
+    );
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            assert_eq!(pixel(&pixels, x, y), [255, 0, 0, 255], "pixel {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn clip_rect_limits_drawing() {
+    let screen = Rect::from_min_max(Pos2::ZERO, Pos2::new(WIDTH as f32, HEIGHT as f32));
+    let clip = Rect::from_min_max(Pos2::new(2.0, 2.0), Pos2::new(4.0, 4.0));
+    let paint_jobs = vec![egui::ClippedMesh(clip, quad(screen, Color32::WHITE))];
+    let pixels = renderer(OutputColorSpace::Srgb).render(
+        &paint_jobs,
+        &screen_descriptor(),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_eq!(pixel(&pixels, 1, 1), [0, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 2, 2), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 3, 3), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 4, 4), [0, 0, 0, 255]);
+}
+
+#[test]
+fn premultiplied_blending_matches_in_both_color_spaces() {
+    let screen = Rect::from_min_max(Pos2::ZERO, Pos2::new(WIDTH as f32, HEIGHT as f32));
+    // Half transparent white over opaque black.
+    let paint_jobs = vec![egui::ClippedMesh(
+        screen,
+        quad(screen, Color32::from_white_alpha(128)),
+    )];
+
+    let unorm = renderer(OutputColorSpace::Srgb).render(
+        &paint_jobs,
+        &screen_descriptor(),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    // Blending in gamma space keeps the premultiplied value.
+    assert_eq!(pixel(&unorm, 0, 0), [128, 128, 128, 255]);
+
+    let srgb = renderer(OutputColorSpace::Linear).render(
+        &paint_jobs,
+        &screen_descriptor(),
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    // Blending in linear space is brighter once encoded back to sRGB.
+    let [r, g, b, a] = pixel(&srgb, 0, 0);
+    assert_eq!((r, a), (188, 255));
+    assert_eq!((r, g), (g, b));
+}
+
+#[test]
+fn sampler_filter_applies_to_user_textures() {
+    let screen = Rect::from_min_max(Pos2::ZERO, Pos2::new(WIDTH as f32, HEIGHT as f32));
+    let id = egui::TextureId::User(0);
+    let mut mesh = egui::paint::Mesh::with_texture(id);
+    mesh.add_rect_with_uv(
+        screen,
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    let paint_jobs = vec![egui::ClippedMesh(screen, mesh)];
+    // One black and one white texel side by side.
+    let texels = [0, 0, 0, 255, 255, 255, 255, 255];
+
+    let mut renderer = renderer(OutputColorSpace::Srgb);
+    renderer.set_user_texture(id, 2, 1, &texels, SamplerDescriptor::NEAREST_REPEAT);
+    let nearest = renderer.render(&paint_jobs, &screen_descriptor(), [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(pixel(&nearest, 3, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(&nearest, 4, 0), [255, 255, 255, 255]);
+
+    renderer.set_user_texture(
+        id,
+        2,
+        1,
+        &texels,
+        SamplerDescriptor {
+            filter: vk::Filter::LINEAR,
+            address_mode: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        },
+    );
+    let linear = renderer.render(&paint_jobs, &screen_descriptor(), [0.0, 0.0, 0.0, 1.0]);
+    // Clamping keeps the outermost pixels at the edge texels.
+    assert_eq!(pixel(&linear, 0, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(&linear, 7, 0), [255, 255, 255, 255]);
+    let [r, _, _, _] = pixel(&linear, 3, 0);
+    assert!(r > 0 && r < 255, "pixel 3 is not filtered: {}", r);
+}
+
+#[test]
+fn viewport_offsets_drawing_and_clipping() {
+    let ui = Rect::from_min_max(Pos2::ZERO, Pos2::new(4.0, 4.0));
+    let paint_jobs = vec![egui::ClippedMesh(ui, quad(ui, Color32::WHITE))];
+    let pixels = renderer(OutputColorSpace::Srgb).render(
+        &paint_jobs,
+        &ScreenDescriptor {
+            viewport: Some(vk::Rect2D {
+                offset: vk::Offset2D { x: 2, y: 2 },
+                extent: vk::Extent2D {
+                    width: 4,
+                    height: 4,
+                },
+            }),
+            ..screen_descriptor()
+        },
+        [0.0, 0.0, 0.0, 1.0],
+    );
+    assert_eq!(pixel(&pixels, 1, 1), [0, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 2, 2), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 5, 5), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 6, 6), [0, 0, 0, 255]);
+}
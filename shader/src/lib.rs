@@ -12,9 +12,9 @@ use spirv_std::macros::spirv;
 
 use spirv_std::{arch, Image, Sampler};
 
-use spirv_std::glam;
+pub use spirv_std::glam;
 
-use glam::{vec3, vec4, BVec3, Vec2, Vec3, Vec4, Vec4Swizzles};
+use glam::{vec3, vec4, BVec3, Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
 
 fn mix(low: Vec3, high: Vec3, x: bool, y: bool, z: bool) -> Vec3 {
     Vec3::new(
@@ -33,18 +33,83 @@ fn mix(low: Vec3, high: Vec3, x: bool, y: bool, z: bool) -> Vec3 {
     )
 }
 
-fn linear_from_srgb(srgb: Vec3) -> Vec3 {
-    let lower = srgb / 3294.6;
-    let higher = ((srgb + vec3(14.025, 14.025, 14.025)) / 269.025).powf(2.4);
+/// Converts a gamma-encoded sRGB colour in `0.0..=1.0` to linear.
+pub fn linear_from_srgb(srgb: Vec3) -> Vec3 {
+    let cutoff = 0.04045;
+    let lower = srgb / 12.92;
+    let higher = ((srgb + vec3(0.055, 0.055, 0.055)) / 1.055).powf(2.4);
     mix(
+        lower,
         higher,
+        srgb.x < cutoff,
+        srgb.y < cutoff,
+        srgb.z < cutoff,
+    )
+}
+
+/// Converts a linear colour in `0.0..=1.0` to gamma-encoded sRGB.
+pub fn srgb_from_linear(linear: Vec3) -> Vec3 {
+    let cutoff = 0.0031308;
+    let lower = linear * 12.92;
+    let higher = linear.powf(1.0 / 2.4) * 1.055 - vec3(0.055, 0.055, 0.055);
+    mix(
         lower,
-        srgb.x < 10.31475,
-        srgb.y < 10.31475,
-        srgb.z < 10.31475,
+        higher,
+        linear.x < cutoff,
+        linear.y < cutoff,
+        linear.z < cutoff,
     )
 }
 
+/// Decodes an `egui::Color32` packed as `u32` (red in the lowest byte) into linear colour.
+///
+/// `Color32` is premultiplied in gamma space, so the colour is unpremultiplied before the sRGB
+/// decode and premultiplied again afterwards. Colours with zero alpha are additive and are
+/// decoded as is.
+pub fn linear_from_color32(color: u32) -> Vec4 {
+    let srgba = Vec4::new(
+        (color & 0xFF) as f32,
+        ((color >> 8) & 0xFF) as f32,
+        ((color >> 16) & 0xFF) as f32,
+        ((color >> 24) & 0xFF) as f32,
+    ) / 255.0;
+    let alpha = srgba.w;
+    if alpha > 0.0 {
+        let linear = linear_from_srgb(srgba.xyz() / alpha) * alpha;
+        Vec4::new(linear.x, linear.y, linear.z, alpha)
+    } else {
+        let linear = linear_from_srgb(srgba.xyz());
+        Vec4::new(linear.x, linear.y, linear.z, alpha)
+    }
+}
+
+/// Encodes a premultiplied linear colour as premultiplied gamma-space sRGB, for UNORM targets.
+pub fn srgb_from_linear_premultiplied(linear: Vec4) -> Vec4 {
+    let alpha = linear.w;
+    if alpha > 0.0 {
+        let srgb = srgb_from_linear(linear.xyz() / alpha) * alpha;
+        Vec4::new(srgb.x, srgb.y, srgb.z, alpha)
+    } else {
+        let srgb = srgb_from_linear(linear.xyz());
+        Vec4::new(srgb.x, srgb.y, srgb.z, alpha)
+    }
+}
+
+/// Per-draw values pushed by `UiPass`, must match its `PushConstants`.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct PushConstants {
+    /// Maps egui points to clip space, used by `main_vs_world`.
+    pub transform: Mat4,
+    /// Clip rect of the paint job in points as min x, min y, max x, max y, passed on by
+    /// `main_vs_world` since scissors do not follow the transform.
+    pub clip_rect: Vec4,
+    /// Depth the paint job is drawn at by `main_vs` and `main_vs_batched`.
+    pub depth: f32,
+    /// Entry of the texture arrays sampled by the fragment shaders of `bindless-shader`.
+    pub texture_index: u32,
+}
+
 #[spirv(vertex)]
 pub fn main_vs(
     // #[spirv(vertex_index)] vert_id: i32,
@@ -55,32 +120,200 @@ pub fn main_vs(
     v_color: &mut Vec4,
     #[spirv(position, invariant)] out_pos: &mut Vec4,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] screen_size: &mut Vec2,
+    #[spirv(push_constant)] push_constants: &PushConstants,
 ) {
-    let color = Vec4::new(
-        (a_color & 0xFF) as f32,
-        ((a_color >> 8) & 0xFF) as f32,
-        ((a_color >> 16) & 0xFF) as f32,
-        ((a_color >> 24) & 0xFF) as f32,
+    *v_color = linear_from_color32(a_color);
+    *out_pos = vec4(
+        2.0 * a_pos.x / screen_size.x - 1.0,
+        1.0 - 2.0 * a_pos.y / screen_size.y,
+        push_constants.depth,
+        1.0,
     );
-    let srgb = linear_from_srgb(color.xyz());
-    *v_color = Vec4::new(srgb.x, srgb.y, srgb.z, color.z / 255.0);
+    *v_tex_coord = a_tex_coord;
+}
+
+/// Vertex shader placing the UI in a 3D scene through `PushConstants::transform`.
+#[spirv(vertex)]
+pub fn main_vs_world(
+    a_pos: Vec2,
+    a_tex_coord: Vec2,
+    a_color: u32,
+    v_tex_coord: &mut Vec2,
+    v_color: &mut Vec4,
+    v_pos: &mut Vec2,
+    #[spirv(flat)] v_clip_rect: &mut Vec4,
+    #[spirv(position, invariant)] out_pos: &mut Vec4,
+    #[spirv(push_constant)] push_constants: &PushConstants,
+) {
+    *v_color = linear_from_color32(a_color);
+    *out_pos = push_constants.transform * vec4(a_pos.x, a_pos.y, 0.0, 1.0);
+    *v_tex_coord = a_tex_coord;
+    *v_pos = a_pos;
+    *v_clip_rect = push_constants.clip_rect;
+}
+
+/// `main_vs` for meshes of several clip rects batched into one draw, each vertex carries the
+/// clip rect of its mesh.
+#[spirv(vertex)]
+pub fn main_vs_batched(
+    a_pos: Vec2,
+    a_tex_coord: Vec2,
+    a_color: u32,
+    a_clip_rect: Vec4,
+    v_tex_coord: &mut Vec2,
+    v_color: &mut Vec4,
+    v_pos: &mut Vec2,
+    #[spirv(flat)] v_clip_rect: &mut Vec4,
+    #[spirv(position, invariant)] out_pos: &mut Vec4,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] screen_size: &mut Vec2,
+    #[spirv(push_constant)] push_constants: &PushConstants,
+) {
+    *v_color = linear_from_color32(a_color);
     *out_pos = vec4(
         2.0 * a_pos.x / screen_size.x - 1.0,
         1.0 - 2.0 * a_pos.y / screen_size.y,
-        0.0,
+        push_constants.depth,
         1.0,
     );
     *v_tex_coord = a_tex_coord;
+    *v_pos = a_pos;
+    *v_clip_rect = a_clip_rect;
+}
+
+/// Discards the fragment if `pos` lies outside of `clip_rect`.
+fn clip(pos: Vec2, clip_rect: Vec4) {
+    if pos.x < clip_rect.x || pos.y < clip_rect.y || pos.x >= clip_rect.z || pos.y >= clip_rect.w {
+        arch::kill();
+    }
 }
 
+/// Fragment shader for `*_SRGB` targets, the hardware encodes the linear output.
 #[spirv(fragment)]
 pub fn main_fs(
     v_tex_coord: Vec2,
     v_color: Vec4,
     #[spirv(descriptor_set = 1, binding = 0)] texture: &Image!(2D, type=f32, sampled),
-    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
     output: &mut Vec4,
 ) {
     *output = texture.sample(*sampler, v_tex_coord);
     *output = v_color * *output;
 }
+
+/// Fragment shader for UNORM targets, the output is encoded to sRGB in the shader.
+#[spirv(fragment)]
+pub fn main_fs_unorm(
+    v_tex_coord: Vec2,
+    v_color: Vec4,
+    #[spirv(descriptor_set = 1, binding = 0)] texture: &Image!(2D, type=f32, sampled),
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    let color: Vec4 = texture.sample(*sampler, v_tex_coord);
+    *output = srgb_from_linear_premultiplied(v_color * color);
+}
+
+/// `main_fs` for `main_vs_world` and `main_vs_batched`, discarding fragments outside the clip
+/// rect.
+#[spirv(fragment)]
+pub fn main_fs_clipped(
+    v_tex_coord: Vec2,
+    v_color: Vec4,
+    v_pos: Vec2,
+    #[spirv(flat)] v_clip_rect: Vec4,
+    #[spirv(descriptor_set = 1, binding = 0)] texture: &Image!(2D, type=f32, sampled),
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    clip(v_pos, v_clip_rect);
+    let color: Vec4 = texture.sample(*sampler, v_tex_coord);
+    *output = v_color * color;
+}
+
+/// `main_fs_unorm` for `main_vs_world` and `main_vs_batched`, discarding fragments outside the
+/// clip rect.
+#[spirv(fragment)]
+pub fn main_fs_unorm_clipped(
+    v_tex_coord: Vec2,
+    v_color: Vec4,
+    v_pos: Vec2,
+    #[spirv(flat)] v_clip_rect: Vec4,
+    #[spirv(descriptor_set = 1, binding = 0)] texture: &Image!(2D, type=f32, sampled),
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    output: &mut Vec4,
+) {
+    clip(v_pos, v_clip_rect);
+    let color: Vec4 = texture.sample(*sampler, v_tex_coord);
+    *output = srgb_from_linear_premultiplied(v_color * color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn linear_from_srgb_reference_values() {
+        // Reference values from the sRGB transfer function in IEC 61966-2-1.
+        let cases = [
+            (0.0, 0.0),
+            (0.04045, 0.0031308),
+            (0.2, 0.0331048),
+            (0.5, 0.2140411),
+            (0.8, 0.6038273),
+            (1.0, 1.0),
+        ];
+        for &(srgb, linear) in &cases {
+            assert_close(linear_from_srgb(Vec3::splat(srgb)).x, linear);
+            assert_close(srgb_from_linear(Vec3::splat(linear)).x, srgb);
+        }
+    }
+
+    #[test]
+    fn srgb_round_trips_every_byte() {
+        for byte in 0..=255u32 {
+            let srgb = byte as f32 / 255.0;
+            let round_trip = srgb_from_linear(linear_from_srgb(Vec3::splat(srgb)));
+            assert_eq!((round_trip.x * 255.0).round() as u32, byte);
+        }
+    }
+
+    #[test]
+    fn color32_uses_alpha_byte() {
+        // Opaque red, `Color32::from_rgb(255, 0, 0)`.
+        let red = linear_from_color32(0xFF_00_00_FF);
+        assert_eq!(red, Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        // Opaque blue must not become transparent.
+        let blue = linear_from_color32(0xFF_FF_00_00);
+        assert_eq!(blue, Vec4::new(0.0, 0.0, 1.0, 1.0));
+
+        // Fully transparent but additive green.
+        let additive = linear_from_color32(0x00_00_FF_00);
+        assert_eq!(additive, Vec4::new(0.0, 1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn color32_is_premultiplied() {
+        // Half transparent white, `Color32::from_white_alpha(128)`.
+        let color = linear_from_color32(0x80_80_80_80);
+        let alpha = 128.0 / 255.0;
+        assert_close(color.w, alpha);
+        assert_close(color.x, alpha);
+
+        // Half transparent 50% grey stays half of the linear value of 50% grey.
+        let color = linear_from_color32(0x80_40_40_40);
+        assert_close(color.x, linear_from_srgb(Vec3::splat(0.5)).x * alpha);
+        let encoded = srgb_from_linear_premultiplied(color);
+        assert_close(encoded.x, 64.0 / 255.0);
+        assert_close(encoded.w, alpha);
+    }
+}